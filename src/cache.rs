@@ -0,0 +1,124 @@
+use std::collections::HashSet;
+use std::fmt::Debug;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::{env, fs};
+
+use sha2::{Digest, Sha256};
+
+use crate::Error;
+
+/// Directory holding the cache when `$XDG_CACHE_HOME` is not set.
+pub const CACHE_DIR_NAME: &str = ".sleek-cache";
+
+/// File in the cache directory listing the keys of formatted files.
+const CACHE_FILE_NAME: &str = "formatted-v2";
+
+/// Remembers files that are already formatted, so they can be skipped.
+///
+/// Each file is identified by a key hashing its contents together with the
+/// settings it was formatted with and the version of sleek, so changing any of
+/// them formats the file again. The keys are the first 128 bits of a SHA-256
+/// digest, so they stay the same across builds and platforms, and are kept
+/// one per line, in hex.
+pub struct Cache {
+    path: PathBuf,
+    known: HashSet<u128>,
+    added: Mutex<HashSet<u128>>,
+}
+
+impl Cache {
+    /// Returns the cache directory: `$XDG_CACHE_HOME/sleek` if that is set,
+    /// otherwise `.sleek-cache` in the current directory.
+    pub fn dir() -> PathBuf {
+        match env::var_os("XDG_CACHE_HOME") {
+            Some(dir) if !dir.is_empty() => PathBuf::from(dir).join("sleek"),
+            _ => PathBuf::from(CACHE_DIR_NAME),
+        }
+    }
+
+    /// Loads the cache from `dir`, starting empty if it does not exist yet.
+    pub fn load(dir: &Path) -> Result<Self, Error> {
+        let path = dir.join(CACHE_FILE_NAME);
+        let known = match fs::read_to_string(&path) {
+            Ok(contents) => contents
+                .lines()
+                .filter_map(|line| u128::from_str_radix(line, 16).ok())
+                .collect(),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => HashSet::new(),
+            Err(source) => return Err(Error::Cache { path, source }),
+        };
+
+        Ok(Self {
+            path,
+            known,
+            added: Mutex::new(HashSet::new()),
+        })
+    }
+
+    /// Returns the key of a file with `contents`, formatted with `settings`.
+    ///
+    /// The settings are hashed through their `Debug` output, which unlike
+    /// `Hash` does not depend on the width or byte order of the platform.
+    pub fn key(contents: &[u8], settings: &impl Debug) -> u128 {
+        let mut hasher = Sha256::new();
+        for part in [env!("CARGO_PKG_VERSION"), &format!("{settings:?}")] {
+            hasher.update((part.len() as u64).to_le_bytes());
+            hasher.update(part);
+        }
+        hasher.update(contents);
+        let digest = hasher.finalize();
+        u128::from_be_bytes(digest[..16].try_into().unwrap())
+    }
+
+    /// Returns true if a file with `key` is known to be formatted.
+    pub fn contains(&self, key: u128) -> bool {
+        self.known.contains(&key)
+    }
+
+    /// Records that a file with `key` is formatted.
+    pub fn insert(&self, key: u128) {
+        if !self.known.contains(&key) {
+            self.added
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .insert(key);
+        }
+    }
+
+    /// Writes the cache back if any keys were added.
+    pub fn save(&self) -> Result<(), Error> {
+        let added = self.added.lock().unwrap_or_else(|e| e.into_inner());
+        if added.is_empty() {
+            return Ok(());
+        }
+
+        let mut keys: Vec<_> = self.known.union(&added).collect();
+        keys.sort();
+        let contents: String = keys.iter().map(|key| format!("{key:032x}\n")).collect();
+
+        let error = |source| Error::Cache {
+            path: self.path.clone(),
+            source,
+        };
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir).map_err(error)?;
+        }
+        fs::write(&self.path, contents).map_err(error)
+    }
+
+    /// Deletes the cache in `dir`, returning false if there was none.
+    pub fn clear(dir: &Path) -> Result<bool, Error> {
+        let path = dir.join(CACHE_FILE_NAME);
+        match fs::remove_file(&path) {
+            Ok(()) => {
+                // Leave the directory if it holds anything else
+                let _ = fs::remove_dir(dir);
+                Ok(true)
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(false),
+            Err(source) => Err(Error::Cache { path, source }),
+        }
+    }
+}
@@ -0,0 +1,267 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use clap::ValueEnum;
+use serde::Deserialize;
+use sqlformat::{FormatOptions, Indent};
+
+use crate::{Dialect, Error};
+
+/// Name of the dedicated configuration file.
+pub const CONFIG_FILE_NAME: &str = "sleek.toml";
+
+/// Name of the Python project file, read from its `[tool.sleek]` table.
+pub const PYPROJECT_FILE_NAME: &str = "pyproject.toml";
+
+/// The line ending style of formatted output.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, ValueEnum, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum EndOfLine {
+    /// Keep the style of the first line ending in the input, or `\n` if there is none
+    #[default]
+    Auto,
+    /// `\n`
+    Lf,
+    /// `\r\n`
+    Crlf,
+}
+
+impl EndOfLine {
+    /// Returns the line ending to use for output formatted from `input`.
+    pub fn resolve(self, input: &str) -> &'static str {
+        match self {
+            EndOfLine::Lf => "\n",
+            EndOfLine::Crlf => "\r\n",
+            EndOfLine::Auto => match input.find('\n') {
+                Some(i) if input[..i].ends_with('\r') => "\r\n",
+                _ => "\n",
+            },
+        }
+    }
+}
+
+/// Fully resolved formatting settings.
+///
+/// New settings may be added in future releases, so start from
+/// [`Config::default`] and override the fields you need.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub struct Config {
+    /// Number of spaces to use for indentation.
+    pub indent_spaces: u8,
+    /// Convert reserved keywords to UPPERCASE.
+    pub uppercase: bool,
+    /// Number of line breaks to insert after each query.
+    pub lines_between_queries: u8,
+    /// Ensure the output ends with a trailing newline.
+    pub trailing_newline: bool,
+    /// SQL dialect to read input as, or `None` to accept a mix of common dialects.
+    pub dialect: Option<Dialect>,
+    /// Line ending style of the output.
+    pub end_of_line: EndOfLine,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            indent_spaces: 4,
+            uppercase: true,
+            lines_between_queries: 2,
+            trailing_newline: true,
+            dialect: None,
+            end_of_line: EndOfLine::Auto,
+        }
+    }
+}
+
+impl Config {
+    /// Returns a copy of this config with every value set in `layer` applied on top.
+    pub fn apply(&self, layer: &ConfigFile) -> Self {
+        Self {
+            indent_spaces: layer.indent_spaces.unwrap_or(self.indent_spaces),
+            uppercase: layer.uppercase.unwrap_or(self.uppercase),
+            lines_between_queries: layer
+                .lines_between_queries
+                .unwrap_or(self.lines_between_queries),
+            trailing_newline: layer.trailing_newline.unwrap_or(self.trailing_newline),
+            dialect: layer.dialect.or(self.dialect),
+            end_of_line: layer.end_of_line.unwrap_or(self.end_of_line),
+        }
+    }
+
+    pub(crate) fn format_options(&self) -> FormatOptions<'static> {
+        FormatOptions {
+            indent: Indent::Spaces(self.indent_spaces),
+            uppercase: Some(self.uppercase),
+            lines_between_queries: self.lines_between_queries,
+            ignore_case_convert: None,
+        }
+    }
+}
+
+/// A partial set of settings, as read from a config file or given on the command line.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct ConfigFile {
+    pub indent_spaces: Option<u8>,
+    pub uppercase: Option<bool>,
+    pub lines_between_queries: Option<u8>,
+    pub trailing_newline: Option<bool>,
+    pub dialect: Option<Dialect>,
+    pub end_of_line: Option<EndOfLine>,
+}
+
+#[derive(Deserialize)]
+struct PyProject {
+    tool: Option<PyProjectTool>,
+}
+
+#[derive(Deserialize)]
+struct PyProjectTool {
+    sleek: Option<ConfigFile>,
+}
+
+impl ConfigFile {
+    /// Loads settings from `path`.
+    ///
+    /// A `pyproject.toml` is read from its `[tool.sleek]` table; any other file is
+    /// read as a `sleek.toml`.
+    pub fn load(path: &Path) -> Result<Self, Error> {
+        match Self::load_if_present(path)? {
+            Some(config) => Ok(config),
+            None => Err(Error::ConfigMissingTable(path.to_path_buf())),
+        }
+    }
+
+    /// Like [`ConfigFile::load`], but returns `None` for a `pyproject.toml` without
+    /// a `[tool.sleek]` table.
+    fn load_if_present(path: &Path) -> Result<Option<Self>, Error> {
+        let contents = fs::read_to_string(path).map_err(|source| Error::ConfigRead {
+            path: path.to_path_buf(),
+            source,
+        })?;
+        let parse_error = |source: toml::de::Error| Error::ConfigParse {
+            path: path.to_path_buf(),
+            source: Box::new(source),
+        };
+
+        if path
+            .file_name()
+            .is_some_and(|name| name == PYPROJECT_FILE_NAME)
+        {
+            let pyproject: PyProject = toml::from_str(&contents).map_err(parse_error)?;
+            Ok(pyproject.tool.and_then(|tool| tool.sleek))
+        } else {
+            toml::from_str(&contents).map(Some).map_err(parse_error)
+        }
+    }
+}
+
+/// Finds and caches the config file that applies to each directory.
+///
+/// Starting from a directory, each ancestor is searched for a `sleek.toml`, then
+/// for a `pyproject.toml` with a `[tool.sleek]` table. The search stops at the
+/// first match or at the repository root (the first directory containing `.git`).
+#[derive(Clone)]
+pub struct ConfigResolver {
+    overrides: ConfigFile,
+    source: Source,
+}
+
+#[derive(Clone)]
+enum Source {
+    Discover(HashMap<PathBuf, Option<ConfigFile>>),
+    Fixed(ConfigFile),
+    Disabled,
+}
+
+impl ConfigResolver {
+    /// Discovers a config file for each path, with `overrides` taking precedence.
+    pub fn discover(overrides: ConfigFile) -> Self {
+        Self {
+            overrides,
+            source: Source::Discover(HashMap::new()),
+        }
+    }
+
+    /// Uses `config` for every path, with `overrides` taking precedence.
+    pub fn fixed(config: ConfigFile, overrides: ConfigFile) -> Self {
+        Self {
+            overrides,
+            source: Source::Fixed(config),
+        }
+    }
+
+    /// Uses only the defaults and `overrides`.
+    pub fn disabled(overrides: ConfigFile) -> Self {
+        Self {
+            overrides,
+            source: Source::Disabled,
+        }
+    }
+
+    /// Returns the effective settings for the file or directory at `path`.
+    pub fn config_for(&mut self, path: &Path) -> Result<Config, Error> {
+        let file = match &mut self.source {
+            Source::Discover(cache) => Self::resolve(cache, path)?,
+            Source::Fixed(config) => Some(config.clone()),
+            Source::Disabled => None,
+        };
+
+        let config = Config::default();
+        let config = match &file {
+            Some(file) => config.apply(file),
+            None => config,
+        };
+        Ok(config.apply(&self.overrides))
+    }
+
+    fn resolve(
+        cache: &mut HashMap<PathBuf, Option<ConfigFile>>,
+        path: &Path,
+    ) -> Result<Option<ConfigFile>, Error> {
+        let path = std::path::absolute(path)?;
+        let dir = if path.is_dir() {
+            path.as_path()
+        } else {
+            path.parent().unwrap_or(&path)
+        };
+        Self::resolve_dir(cache, dir)
+    }
+
+    fn resolve_dir(
+        cache: &mut HashMap<PathBuf, Option<ConfigFile>>,
+        dir: &Path,
+    ) -> Result<Option<ConfigFile>, Error> {
+        if let Some(cached) = cache.get(dir) {
+            return Ok(cached.clone());
+        }
+
+        let found = match Self::find_in_dir(dir)? {
+            Some(config) => Some(config),
+            None if dir.join(".git").exists() => None,
+            None => match dir.parent() {
+                Some(parent) => Self::resolve_dir(cache, parent)?,
+                None => None,
+            },
+        };
+
+        cache.insert(dir.to_path_buf(), found.clone());
+        Ok(found)
+    }
+
+    fn find_in_dir(dir: &Path) -> Result<Option<ConfigFile>, Error> {
+        let sleek_toml = dir.join(CONFIG_FILE_NAME);
+        if sleek_toml.is_file() {
+            return ConfigFile::load(&sleek_toml).map(Some);
+        }
+
+        let pyproject = dir.join(PYPROJECT_FILE_NAME);
+        if pyproject.is_file() {
+            return ConfigFile::load_if_present(&pyproject);
+        }
+
+        Ok(None)
+    }
+}
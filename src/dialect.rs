@@ -0,0 +1,399 @@
+use clap::ValueEnum;
+use serde::Deserialize;
+
+use crate::Config;
+use crate::routines;
+use crate::statements::{TokenKind, token_at};
+
+/// The SQL dialect that decides how identifiers, strings, operators and keywords are read.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, ValueEnum, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Dialect {
+    /// PostgreSQL
+    Postgres,
+    /// MySQL and MariaDB
+    Mysql,
+    /// SQLite
+    Sqlite,
+    /// Microsoft SQL Server (T-SQL)
+    Tsql,
+    /// Google BigQuery
+    Bigquery,
+    /// Snowflake
+    Snowflake,
+    /// Standard SQL
+    Ansi,
+}
+
+/// The prefix of a string literal, such as the `N` in `N'text'`.
+pub(crate) struct StringPrefix {
+    /// Length of the prefix in bytes.
+    pub len: usize,
+    /// Whether backslashes escape, if the prefix overrides the dialect's default.
+    pub backslash: Option<bool>,
+}
+
+impl Dialect {
+    /// Returns true if `#` starts a line comment.
+    pub(crate) fn hash_comments(self) -> bool {
+        matches!(self, Dialect::Mysql | Dialect::Bigquery)
+    }
+
+    /// Returns true if `` `name` `` is a quoted identifier.
+    pub(crate) fn backtick_identifiers(self) -> bool {
+        matches!(self, Dialect::Mysql | Dialect::Sqlite | Dialect::Bigquery)
+    }
+
+    /// Returns true if `[name]` is a quoted identifier rather than a subscript.
+    pub(crate) fn bracket_identifiers(self) -> bool {
+        matches!(self, Dialect::Sqlite | Dialect::Tsql)
+    }
+
+    /// Returns true if `"text"` is a string literal rather than a quoted identifier.
+    pub(crate) fn double_quoted_strings(self) -> bool {
+        matches!(self, Dialect::Mysql | Dialect::Bigquery)
+    }
+
+    /// Returns true if a backslash escapes the next character in string literals.
+    pub(crate) fn backslash_escapes(self) -> bool {
+        matches!(
+            self,
+            Dialect::Mysql | Dialect::Bigquery | Dialect::Snowflake
+        )
+    }
+
+    /// Returns true if `'''text'''` and `"""text"""` are string literals.
+    pub(crate) fn triple_quoted_strings(self) -> bool {
+        self == Dialect::Bigquery
+    }
+
+    /// Returns the string prefix `word` forms with the quote at the start of `rest`, if any.
+    pub(crate) fn string_prefix(self, word: &str, rest: &[u8]) -> Option<StringPrefix> {
+        let prefix = |len, backslash| Some(StringPrefix { len, backslash });
+
+        match (self, word.to_ascii_uppercase().as_str(), rest) {
+            (_, "N" | "B" | "X", [b'\'', ..]) => prefix(1, None),
+            (Dialect::Postgres, "E", [b'\'', ..]) => prefix(1, Some(true)),
+            (Dialect::Postgres, "U", [b'&', b'\'', ..]) => prefix(2, Some(false)),
+            (Dialect::Bigquery, "B", [b'"', ..]) => prefix(1, None),
+            (Dialect::Bigquery, "R" | "RB" | "BR", [b'\'' | b'"', ..]) => {
+                prefix(word.len(), Some(false))
+            }
+            _ => None,
+        }
+    }
+
+    /// Keywords of this dialect that sqlformat does not know, so their case
+    /// would otherwise be left alone.
+    fn keywords(self) -> &'static [&'static str] {
+        match self {
+            Dialect::Postgres => &[
+                "ARRAY", "CONFLICT", "ILIKE", "LANGUAGE", "LATERAL", "NOTHING", "SIMILAR",
+            ],
+            Dialect::Mysql => &["DUAL"],
+            Dialect::Sqlite => &["AUTOINCREMENT", "GLOB", "PRAGMA", "VACUUM"],
+            Dialect::Tsql => &["NOLOCK", "OUTPUT", "PIVOT", "TOP", "UNPIVOT"],
+            Dialect::Bigquery => &["ARRAY", "QUALIFY", "STRUCT", "UNNEST"],
+            Dialect::Snowflake => &["ILIKE", "LATERAL", "PIVOT", "QUALIFY", "UNPIVOT"],
+            Dialect::Ansi => &["LATERAL", "SIMILAR"],
+        }
+    }
+
+    /// Returns true if `@name` and `@@name` are variables or parameters rather
+    /// than operators.
+    pub(crate) fn at_variables(self) -> bool {
+        matches!(
+            self,
+            Dialect::Mysql | Dialect::Sqlite | Dialect::Tsql | Dialect::Bigquery
+        )
+    }
+
+    /// Returns true if `$$text$$` and `$tag$text$tag$` are string literals.
+    pub(crate) fn dollar_quoted_strings(self) -> bool {
+        matches!(self, Dialect::Postgres | Dialect::Snowflake)
+    }
+
+    /// Decides how the token of `kind` spanning `start..end` is rewritten for
+    /// sqlformat, or returns `None` to keep it as written.
+    fn rewrite(self, sql: &str, start: usize, end: usize, kind: TokenKind) -> Option<Rewrite> {
+        let bytes = sql.as_bytes();
+        let after_word = start > 0 && is_word_like(sql[..start].chars().next_back());
+        let placeholder = |attached, end| Some(Rewrite::Placeholder { attached, end });
+
+        match (kind, bytes[start]) {
+            (TokenKind::Quoted, _) => placeholder(false, end),
+            // Only T-SQL clients read `GO`, which sqlformat would break out of
+            // the query as a batch separator
+            (TokenKind::Word, _)
+                if self != Dialect::Tsql && sql[start..end].eq_ignore_ascii_case("GO") =>
+            {
+                placeholder(false, end)
+            }
+            (TokenKind::Word, _) => self
+                .keywords()
+                .iter()
+                .find(|k| k.eq_ignore_ascii_case(&sql[start..end]))
+                .map(|keyword| Rewrite::Keyword(keyword)),
+            // T-SQL temporary tables, which sqlformat would read as comments
+            (TokenKind::Other, b'#') if self == Dialect::Tsql => {
+                placeholder(false, skip_word(sql, skip_while(sql, start, &['#'])))
+            }
+            // Variables such as `@p`, `@@version` and MySQL's `@'name'`, which
+            // sqlformat would split after the `@`
+            (TokenKind::Other, b'@') if self.at_variables() => {
+                let name = skip_while(sql, start, &['@']);
+                if name - start > 2 || name == sql.len() {
+                    return None;
+                }
+                match token_at(sql, name, Some(self)) {
+                    (TokenKind::Word | TokenKind::Quoted, end) => placeholder(false, end),
+                    _ => None,
+                }
+            }
+            // Operators starting with `#`, such as Postgres's `#>` and `#-`
+            (TokenKind::Other, b'#') => placeholder(false, skip_while(sql, start + 1, &['>', '-'])),
+            // Snowflake semi-structured paths, which sqlformat would read as a
+            // named placeholder
+            (TokenKind::Other, b':')
+                if self == Dialect::Snowflake
+                    && (after_word || start > 0 && matches!(bytes[start - 1], b'"' | b']'))
+                    && bytes
+                        .get(start + 1)
+                        .is_some_and(|b| b.is_ascii_alphabetic() || *b == b'"') =>
+            {
+                placeholder(true, self.skip_path(sql, start))
+            }
+            // Array subscripts and types, which sqlformat would read as a quoted
+            // identifier
+            (TokenKind::Other, b'[')
+                if after_word || (start > 0 && matches!(bytes[start - 1], b')' | b']')) =>
+            {
+                placeholder(true, self.skip_brackets(sql, start))
+            }
+            _ => None,
+        }
+    }
+
+    /// Skips a Snowflake path such as `:field.nested[0]` starting at the `:` at `start`.
+    fn skip_path(self, sql: &str, start: usize) -> usize {
+        let mut pos = start + 1;
+        while pos < sql.len() {
+            pos = match sql.as_bytes()[pos] {
+                b'.' | b':' if sql[pos..].starts_with("::") => break,
+                b'.' | b':' => pos + 1,
+                b'[' => self.skip_brackets(sql, pos),
+                _ => match token_at(sql, pos, Some(self)) {
+                    (TokenKind::Word | TokenKind::Quoted, end) => end,
+                    _ => break,
+                },
+            };
+        }
+        pos
+    }
+
+    /// Skips balanced brackets starting at the `[` at `start`.
+    fn skip_brackets(self, sql: &str, start: usize) -> usize {
+        let mut depth = 0;
+        let mut pos = start;
+        while pos < sql.len() {
+            let (kind, end) = token_at(sql, pos, Some(self));
+            if kind == TokenKind::Other {
+                match sql.as_bytes()[pos] {
+                    b'[' => depth += 1,
+                    b']' => {
+                        depth -= 1;
+                        if depth == 0 {
+                            return end;
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            pos = end;
+        }
+        pos
+    }
+}
+
+/// How a token is rewritten before the text is passed to sqlformat.
+enum Rewrite {
+    /// Replace a keyword with its canonical spelling.
+    Keyword(&'static str),
+    /// Pass a keyword sqlformat knows in upper case, which it then converts to
+    /// the configured case.
+    Uppercase,
+    /// Replace everything up to `end` with a placeholder word.
+    Placeholder { attached: bool, end: usize },
+}
+
+/// Rewrites `sql` so that sqlformat, which only understands a blend of
+/// dialects, reads it the way `dialect` does.
+///
+/// Strings, quoted identifiers and dialect-specific operators are replaced by
+/// placeholder words that sqlformat formats like any identifier, and the
+/// dialect's keywords get the same case as the ones sqlformat knows. Without a
+/// dialect, only dollar-quoted strings are replaced, and `BETWEEN ... AND` is
+/// passed in upper case so it is formatted the same whatever its case.
+pub(crate) fn mask(sql: &str, dialect: Option<Dialect>, uppercase: bool) -> Masked {
+    let mut prefix = String::from("sleekmask");
+    while sql.contains(&prefix) {
+        prefix.push('x');
+    }
+    let mut masked = Masked {
+        text: String::with_capacity(sql.len()),
+        prefix,
+        originals: Vec::new(),
+    };
+
+    let mut pos = 0;
+    while pos < sql.len() {
+        let (kind, end) = token_at(sql, pos, dialect);
+        let rewrite = match (kind, dialect) {
+            (TokenKind::DollarQuoted, _) => Some(Rewrite::Placeholder {
+                attached: false,
+                end,
+            }),
+            // sqlformat only keeps `BETWEEN x AND y` on one line when both are
+            // upper case, so the output would change when formatted again
+            (TokenKind::Word, _)
+                if ["BETWEEN", "AND"]
+                    .iter()
+                    .any(|k| k.eq_ignore_ascii_case(&sql[pos..end])) =>
+            {
+                Some(Rewrite::Uppercase)
+            }
+            (_, Some(dialect)) => dialect.rewrite(sql, pos, end, kind),
+            (_, None) => None,
+        };
+
+        match rewrite {
+            Some(Rewrite::Placeholder { attached, end }) => {
+                let language = match kind {
+                    TokenKind::DollarQuoted => routines::body_language(sql, pos, dialect),
+                    _ => None,
+                };
+                masked.push(
+                    &sql[pos..end],
+                    attached,
+                    language,
+                    sql[end..].chars().next(),
+                );
+                pos = end;
+            }
+            Some(Rewrite::Keyword(keyword)) if uppercase => {
+                masked.text.push_str(keyword);
+                pos = end;
+            }
+            Some(Rewrite::Keyword(keyword)) => {
+                masked.text.push_str(&keyword.to_lowercase());
+                pos = end;
+            }
+            Some(Rewrite::Uppercase) => {
+                masked.text.push_str(&sql[pos..end].to_ascii_uppercase());
+                pos = end;
+            }
+            None => {
+                masked.text.push_str(&sql[pos..end]);
+                pos = end;
+            }
+        }
+    }
+
+    masked
+}
+
+/// SQL with parts replaced by placeholder words, as returned by [`mask`].
+pub(crate) struct Masked {
+    pub text: String,
+    prefix: String,
+    originals: Vec<Original>,
+}
+
+/// Text replaced by a placeholder.
+struct Original {
+    text: String,
+    /// Whether it follows the preceding token without a space.
+    attached: bool,
+    /// The language of the routine body it holds, if it is one.
+    language: Option<String>,
+}
+
+impl Masked {
+    fn push(&mut self, text: &str, attached: bool, language: Option<String>, next: Option<char>) {
+        // Keep the placeholder a separate word from its neighbours
+        if attached || is_word_like(self.text.chars().next_back()) {
+            self.text.push(' ');
+        }
+        self.text.push_str(&self.prefix);
+        self.text.push_str(&self.originals.len().to_string());
+        self.text.push('_');
+        if is_word_like(next) {
+            self.text.push(' ');
+        }
+        self.originals.push(Original {
+            text: text.to_string(),
+            attached,
+            language,
+        });
+    }
+
+    /// Puts the original text back in place of each placeholder in `formatted`,
+    /// formatting the routine bodies among them according to `config`.
+    pub fn unmask(&self, formatted: &str, config: &Config) -> String {
+        let mut text = String::with_capacity(formatted.len());
+        let mut rest = formatted;
+
+        while let Some(start) = rest.find(&self.prefix) {
+            text.push_str(&rest[..start]);
+            let after = &rest[start + self.prefix.len()..];
+            let digits = after.len() - after.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+
+            let Some(original) = after[..digits]
+                .parse()
+                .ok()
+                .and_then(|index: usize| self.originals.get(index))
+            else {
+                text.push_str(&self.prefix);
+                rest = after;
+                continue;
+            };
+
+            if original.attached {
+                text.truncate(text.trim_end_matches(' ').len());
+            }
+            let line = &text[text.rfind('\n').map_or(0, |i| i + 1)..];
+            let indent = &line[..line.len() - line.trim_start().len()];
+            let body = original.language.as_deref().and_then(|language| {
+                routines::format_body(&original.text, language, indent, config)
+            });
+            text.push_str(body.as_deref().unwrap_or(&original.text));
+            rest = after[digits..]
+                .strip_prefix('_')
+                .unwrap_or(&after[digits..]);
+        }
+
+        text.push_str(rest);
+        text
+    }
+}
+
+/// Returns true if `c` would join a placeholder word next to it.
+fn is_word_like(c: Option<char>) -> bool {
+    c.is_some_and(|c| c.is_alphanumeric() || matches!(c, '_' | '$' | '@' | '#'))
+}
+
+/// Returns the offset of the first character at or after `start` not in `chars`.
+fn skip_while(sql: &str, start: usize, chars: &[char]) -> usize {
+    sql.len() - sql[start..].trim_start_matches(chars).len()
+}
+
+/// Returns the end of the word starting at `start`, or `start` if there is none.
+fn skip_word(sql: &str, start: usize) -> usize {
+    match start < sql.len() {
+        true => match token_at(sql, start, None) {
+            (TokenKind::Word, end) => end,
+            _ => start,
+        },
+        false => start,
+    }
+}
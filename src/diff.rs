@@ -0,0 +1,89 @@
+use std::io::{self, IsTerminal};
+use std::ops::RangeInclusive;
+
+use clap::ValueEnum;
+use similar::TextDiff;
+
+const RESET: &str = "\x1b[0m";
+const BOLD: &str = "\x1b[1m";
+const RED: &str = "\x1b[31m";
+const GREEN: &str = "\x1b[32m";
+const CYAN: &str = "\x1b[36m";
+
+/// When to colorize diff output.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum ColorChoice {
+    /// Colorize when stdout is a terminal
+    #[default]
+    Auto,
+    /// Always colorize
+    Always,
+    /// Never colorize
+    Never,
+}
+
+impl ColorChoice {
+    pub fn enabled(self) -> bool {
+        match self {
+            ColorChoice::Auto => io::stdout().is_terminal(),
+            ColorChoice::Always => true,
+            ColorChoice::Never => false,
+        }
+    }
+}
+
+/// Renders a unified diff from `original` to `formatted`, using `name` in both
+/// file headers.
+pub fn unified_diff(name: &str, original: &str, formatted: &str, color: bool) -> String {
+    let diff = TextDiff::from_lines(original, formatted)
+        .unified_diff()
+        .context_radius(3)
+        .header(name, name)
+        .to_string();
+
+    if !color {
+        return diff;
+    }
+
+    diff.split_inclusive('\n')
+        .map(|line| {
+            let style = if line.starts_with("---") || line.starts_with("+++") {
+                BOLD
+            } else if line.starts_with("@@") {
+                CYAN
+            } else if line.starts_with('-') {
+                RED
+            } else if line.starts_with('+') {
+                GREEN
+            } else {
+                return line.to_string();
+            };
+
+            match line.strip_suffix('\n') {
+                Some(content) => format!("{style}{content}{RESET}\n"),
+                None => format!("{style}{line}{RESET}"),
+            }
+        })
+        .collect()
+}
+
+/// Returns the 1-based, inclusive ranges of lines in `original` that formatting
+/// replaces. Lines only inserted are reported at the line they come before, or
+/// the last line if they are appended.
+pub fn changed_lines(original: &str, formatted: &str) -> Vec<RangeInclusive<usize>> {
+    let diff = TextDiff::from_lines(original, formatted);
+    let last_line = original.lines().count().max(1);
+
+    diff.grouped_ops(0)
+        .iter()
+        .filter_map(|group| {
+            let start = group.first()?.old_range().start;
+            let end = group.last()?.old_range().end;
+            let line = (start + 1).min(last_line);
+            match start == end {
+                true => Some(line..=line),
+                false => Some(start + 1..=end),
+            }
+        })
+        .collect()
+}
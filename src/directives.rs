@@ -0,0 +1,310 @@
+use std::ops::Range;
+
+use crate::Dialect;
+use crate::statements::{self, TokenKind, next_line, statement_end, token_at};
+
+/// A formatter directive written as a `-- sleek: <directive>` comment on its own line.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Directive {
+    /// Stop formatting until the next `on` directive.
+    Off,
+    /// Resume formatting after an `off` directive.
+    On,
+    /// Leave the next statement as written.
+    Skip,
+    /// Leave the whole file as written; only honoured in the leading comments.
+    IgnoreFile,
+}
+
+impl Directive {
+    fn parse(line: &str) -> Option<Self> {
+        let directive = line
+            .trim()
+            .strip_prefix("--")?
+            .trim_start()
+            .strip_prefix("sleek:")?
+            .trim();
+
+        match directive {
+            "off" => Some(Directive::Off),
+            "on" => Some(Directive::On),
+            "skip" => Some(Directive::Skip),
+            "ignore-file" => Some(Directive::IgnoreFile),
+            _ => None,
+        }
+    }
+}
+
+/// A line interpreted by the SQL client rather than sent to the server.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ClientCommand<'a> {
+    /// A T-SQL batch separator, `GO` with an optional repeat count.
+    BatchSeparator,
+    /// A MySQL `DELIMITER` line, changing the statement terminator.
+    Delimiter(&'a str),
+    /// A psql meta-command such as `\set` or `\copy`.
+    MetaCommand,
+}
+
+impl<'a> ClientCommand<'a> {
+    /// Parses `line` as a command of the client for `dialect`, or of any client
+    /// without a dialect.
+    fn parse(line: &'a str, dialect: Option<Dialect>) -> Option<Self> {
+        let line = line.trim();
+        let mut words = line.split_whitespace();
+        let first = words.next()?;
+        let reads = |client: Dialect| dialect.is_none_or(|dialect| dialect == client);
+
+        if line.starts_with('\\') && reads(Dialect::Postgres) {
+            Some(ClientCommand::MetaCommand)
+        } else if first.eq_ignore_ascii_case("GO") && reads(Dialect::Tsql) {
+            match (words.next(), words.next()) {
+                (None, _) => Some(ClientCommand::BatchSeparator),
+                (Some(count), None) if count.parse::<u32>().is_ok() => {
+                    Some(ClientCommand::BatchSeparator)
+                }
+                _ => None,
+            }
+        } else if first.eq_ignore_ascii_case("DELIMITER") && reads(Dialect::Mysql) {
+            match (words.next(), words.next()) {
+                (Some(delimiter), None) => Some(ClientCommand::Delimiter(delimiter)),
+                _ => None,
+            }
+        } else {
+            None
+        }
+    }
+}
+
+/// A region of the input, either to be formatted or passed through unchanged.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Segment<'a> {
+    Format(&'a str),
+    /// Statements terminated by a `DELIMITER` other than `;`.
+    Delimited {
+        text: &'a str,
+        delimiter: &'a str,
+    },
+    Verbatim(&'a str),
+}
+
+/// Returns true if the leading comments of `input` contain `-- sleek: ignore-file`.
+pub fn is_ignored_file(input: &str) -> bool {
+    input
+        .lines()
+        .take_while(|line| line.trim().is_empty() || line.trim_start().starts_with("--"))
+        .any(|line| Directive::parse(line) == Some(Directive::IgnoreFile))
+}
+
+/// Splits `input` into regions to format and regions protected by directives.
+///
+/// A `-- sleek: off` line protects everything up to and including the next
+/// `-- sleek: on` line, or the rest of the input if there is none. A
+/// `-- sleek: skip` line protects itself and the statement that follows it,
+/// up to the end of the line holding its terminating semicolon.
+///
+/// Client-side lines, such as T-SQL's `GO`, MySQL's `DELIMITER //` and psql's
+/// `\set`, are kept as written and split the input into batches that are
+/// formatted separately. With a dialect, its client's lines are recognised
+/// wherever the client reads them, at the start of any line. Without one, they
+/// are only recognised between statements, so a column named `go` on its own
+/// line is left in its query. Statements after a `DELIMITER` line are
+/// terminated by its delimiter until the next `DELIMITER` line.
+pub fn segments(input: &str, dialect: Option<Dialect>) -> Vec<Segment<'_>> {
+    let mut segments = Vec::new();
+    let mut delimiter = ";";
+    let mut format_start = 0;
+    let mut pos = 0;
+    // Whether a statement has started and not been terminated
+    let mut in_statement = false;
+
+    while pos < input.len() {
+        let line_end = next_line(input, pos);
+        let line = &input[pos..line_end];
+        let command =
+            ClientCommand::parse(line, dialect).filter(|_| dialect.is_some() || !in_statement);
+
+        let verbatim_end = match (Directive::parse(line), command) {
+            (Some(Directive::Off), _) => {
+                let mut end = line_end;
+                while end < input.len() {
+                    let next = next_line(input, end);
+                    let directive = Directive::parse(&input[end..next]);
+                    end = next;
+                    if directive == Some(Directive::On) {
+                        break;
+                    }
+                }
+                Some(end)
+            }
+            (Some(Directive::Skip), _) => Some(match statement_end(&input[line_end..], dialect) {
+                Some(offset) => next_line(input, line_end + offset),
+                None => input.len(),
+            }),
+            (_, Some(_)) => {
+                // Consecutive client-side lines stay together
+                let mut end = line_end;
+                while end < input.len() {
+                    let next = next_line(input, end);
+                    if ClientCommand::parse(&input[end..next], dialect).is_none() {
+                        break;
+                    }
+                    end = next;
+                }
+                Some(end)
+            }
+            _ => None,
+        };
+
+        match verbatim_end {
+            Some(end) => {
+                segments.push(format_segment(&input[format_start..pos], delimiter));
+                segments.push(Segment::Verbatim(&input[pos..end]));
+
+                // The client still reads `DELIMITER` lines in protected regions
+                let last_delimiter = input[pos..end].lines().rev().find_map(|line| {
+                    match ClientCommand::parse(line, dialect) {
+                        Some(ClientCommand::Delimiter(delimiter)) => Some(delimiter),
+                        _ => None,
+                    }
+                });
+                delimiter = last_delimiter.unwrap_or(delimiter);
+
+                format_start = end;
+                pos = end;
+                in_statement = false;
+            }
+            None => pos = skip_line(input, pos, delimiter, dialect, &mut in_statement),
+        }
+    }
+
+    segments.push(format_segment(&input[format_start..], delimiter));
+    segments.retain(|segment| match segment {
+        Segment::Format(text) | Segment::Delimited { text, .. } => !text.trim().is_empty(),
+        Segment::Verbatim(_) => true,
+    });
+    segments
+}
+
+/// A statement outside the regions protected by directives and client-side
+/// lines.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Statement<'a> {
+    /// Byte range of the statement in the input, including its terminator.
+    pub range: Range<usize>,
+    /// Index of the segment holding it; statements of one segment form a batch.
+    pub segment: usize,
+    /// Its terminator, when a `DELIMITER` line set one other than `;`.
+    pub delimiter: Option<&'a str>,
+}
+
+/// Lists the statements of `input` that may be formatted, skipping everything
+/// in [`Segment::Verbatim`] regions.
+pub fn statements(input: &str, dialect: Option<Dialect>) -> Vec<Statement<'_>> {
+    let offset = |text: &str| text.as_ptr() as usize - input.as_ptr() as usize;
+    let mut statements = Vec::new();
+
+    for (segment, part) in segments(input, dialect).into_iter().enumerate() {
+        match part {
+            Segment::Format(text) => {
+                let start = offset(text);
+                statements.extend(statements::statement_spans(text, dialect).into_iter().map(
+                    |span| Statement {
+                        range: start + span.start..start + span.end,
+                        segment,
+                        delimiter: None,
+                    },
+                ));
+            }
+            Segment::Delimited { text, delimiter } => {
+                for (statement, terminated) in split_delimited(text, delimiter, dialect) {
+                    let start =
+                        offset(statement) + (statement.len() - statement.trim_start().len());
+                    let end = match terminated {
+                        true => offset(statement) + statement.len() + delimiter.len(),
+                        false => offset(statement) + statement.trim_end().len(),
+                    };
+                    statements.push(Statement {
+                        range: start..end,
+                        segment,
+                        delimiter: Some(delimiter),
+                    });
+                }
+            }
+            Segment::Verbatim(_) => {}
+        }
+    }
+
+    statements
+}
+
+fn format_segment<'a>(text: &'a str, delimiter: &'a str) -> Segment<'a> {
+    match delimiter {
+        ";" => Segment::Format(text),
+        delimiter => Segment::Delimited { text, delimiter },
+    }
+}
+
+/// Returns the start of the first line after the one at `pos` that does not
+/// begin inside a multi-line string or comment, tracking whether the lines
+/// skipped leave a statement unterminated.
+fn skip_line(
+    input: &str,
+    pos: usize,
+    delimiter: &str,
+    dialect: Option<Dialect>,
+    in_statement: &mut bool,
+) -> usize {
+    let mut end = next_line(input, pos);
+    let mut i = pos;
+    while i < end {
+        i = match input[i..].starts_with(delimiter) {
+            true => {
+                *in_statement = false;
+                i + delimiter.len()
+            }
+            false => {
+                let (kind, token_end) = token_at(input, i, dialect);
+                if kind != TokenKind::Comment && !input[i..token_end].trim().is_empty() {
+                    *in_statement = true;
+                }
+                token_end
+            }
+        };
+        if i > end {
+            end = next_line(input, i);
+        }
+    }
+    end
+}
+
+/// Splits `text` into statements terminated by `delimiter`, returning each
+/// statement without it and whether it was terminated.
+pub fn split_delimited<'a>(
+    text: &'a str,
+    delimiter: &str,
+    dialect: Option<Dialect>,
+) -> Vec<(&'a str, bool)> {
+    let mut statements = Vec::new();
+    let mut start = 0;
+    let mut pos = 0;
+
+    while pos < text.len() {
+        if text[pos..].starts_with(delimiter) {
+            statements.push((&text[start..pos], true));
+            pos += delimiter.len();
+            start = pos;
+            continue;
+        }
+        pos = match token_at(text, pos, dialect) {
+            // Only step over strings and comments, so a delimiter that starts
+            // with punctuation is still found between other tokens
+            (TokenKind::Quoted | TokenKind::Comment | TokenKind::DollarQuoted, end) => end,
+            _ => pos + text[pos..].chars().next().map_or(1, char::len_utf8),
+        };
+    }
+
+    statements.push((&text[start..], false));
+    statements.retain(|(statement, _)| !statement.trim().is_empty());
+    statements
+}
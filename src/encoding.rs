@@ -0,0 +1,152 @@
+use std::fmt;
+
+use clap::ValueEnum;
+
+const UTF8_BOM: &[u8] = &[0xEF, 0xBB, 0xBF];
+const UTF16LE_BOM: &[u8] = &[0xFF, 0xFE];
+const UTF16BE_BOM: &[u8] = &[0xFE, 0xFF];
+
+/// Characters of Windows-1252 bytes 0x80 to 0x9F, where it differs from Latin-1.
+/// Bytes it leaves undefined map to the C1 control character of the same value.
+const WINDOWS_1252_HIGH: [char; 32] = [
+    '\u{20AC}', '\u{81}', '\u{201A}', '\u{192}', '\u{201E}', '\u{2026}', '\u{2020}', '\u{2021}',
+    '\u{2C6}', '\u{2030}', '\u{160}', '\u{2039}', '\u{152}', '\u{8D}', '\u{17D}', '\u{8F}',
+    '\u{90}', '\u{2018}', '\u{2019}', '\u{201C}', '\u{201D}', '\u{2022}', '\u{2013}', '\u{2014}',
+    '\u{2DC}', '\u{2122}', '\u{161}', '\u{203A}', '\u{153}', '\u{9D}', '\u{17E}', '\u{178}',
+];
+
+/// The character encoding of a file.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, ValueEnum)]
+pub enum Encoding {
+    /// Detect UTF-8 or UTF-16 from a byte order mark, otherwise require UTF-8
+    #[default]
+    Auto,
+    #[value(name = "utf-8")]
+    Utf8,
+    #[value(name = "utf-16le")]
+    Utf16Le,
+    #[value(name = "utf-16be")]
+    Utf16Be,
+    /// ISO-8859-1
+    Latin1,
+    #[value(name = "windows-1252")]
+    Windows1252,
+}
+
+impl fmt::Display for Encoding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.to_possible_value() {
+            Some(value) => f.write_str(value.get_name()),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Text decoded from a file, along with what is needed to encode it the same way.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Decoded {
+    pub text: String,
+    /// The encoding the text was read with, never [`Encoding::Auto`].
+    pub encoding: Encoding,
+    /// Whether the file started with a byte order mark.
+    pub bom: bool,
+}
+
+impl Decoded {
+    /// Encodes `text` the way the original was, including any byte order mark.
+    ///
+    /// Returns `None` if `text` has characters the encoding cannot represent.
+    pub fn encode(&self, text: &str) -> Option<Vec<u8>> {
+        let mut bytes = Vec::with_capacity(text.len() + 3);
+        let bom = match self.encoding {
+            Encoding::Auto | Encoding::Utf8 => UTF8_BOM,
+            Encoding::Utf16Le => UTF16LE_BOM,
+            Encoding::Utf16Be => UTF16BE_BOM,
+            Encoding::Latin1 | Encoding::Windows1252 => &[],
+        };
+        if self.bom {
+            bytes.extend_from_slice(bom);
+        }
+
+        match self.encoding {
+            Encoding::Auto | Encoding::Utf8 => bytes.extend_from_slice(text.as_bytes()),
+            Encoding::Utf16Le => bytes.extend(text.encode_utf16().flat_map(u16::to_le_bytes)),
+            Encoding::Utf16Be => bytes.extend(text.encode_utf16().flat_map(u16::to_be_bytes)),
+            Encoding::Latin1 => {
+                for c in text.chars() {
+                    bytes.push(u8::try_from(c).ok()?);
+                }
+            }
+            Encoding::Windows1252 => {
+                for c in text.chars() {
+                    let byte = match WINDOWS_1252_HIGH.iter().position(|&high| high == c) {
+                        Some(i) => 0x80 + i as u8,
+                        None => u8::try_from(c).ok().filter(|b| !(0x80..0xA0).contains(b))?,
+                    };
+                    bytes.push(byte);
+                }
+            }
+        }
+
+        Some(bytes)
+    }
+}
+
+/// Decodes `bytes` as `encoding`, stripping any byte order mark.
+///
+/// With [`Encoding::Auto`], a byte order mark selects UTF-8 or UTF-16, and
+/// anything else must be valid UTF-8 without NUL bytes, which are far more
+/// likely to come from UTF-16 without a byte order mark than from SQL. Returns
+/// `None` if the bytes are not valid in the encoding.
+pub fn decode(bytes: &[u8], encoding: Encoding) -> Option<Decoded> {
+    let encoding = match encoding {
+        Encoding::Auto if bytes.starts_with(UTF8_BOM) => Encoding::Utf8,
+        Encoding::Auto if bytes.starts_with(UTF16LE_BOM) => Encoding::Utf16Le,
+        Encoding::Auto if bytes.starts_with(UTF16BE_BOM) => Encoding::Utf16Be,
+        Encoding::Auto if bytes.contains(&0) => return None,
+        Encoding::Auto => Encoding::Utf8,
+        encoding => encoding,
+    };
+
+    let bom = match encoding {
+        Encoding::Auto | Encoding::Utf8 => UTF8_BOM,
+        Encoding::Utf16Le => UTF16LE_BOM,
+        Encoding::Utf16Be => UTF16BE_BOM,
+        Encoding::Latin1 | Encoding::Windows1252 => &[],
+    };
+    let (bom, bytes) = match bytes.strip_prefix(bom) {
+        Some(rest) if !bom.is_empty() => (true, rest),
+        _ => (false, bytes),
+    };
+
+    let text = match encoding {
+        Encoding::Auto | Encoding::Utf8 => String::from_utf8(bytes.to_vec()).ok()?,
+        Encoding::Utf16Le | Encoding::Utf16Be => {
+            if bytes.len() % 2 != 0 {
+                return None;
+            }
+            let units: Vec<u16> = bytes
+                .chunks_exact(2)
+                .map(|pair| match encoding {
+                    Encoding::Utf16Le => u16::from_le_bytes([pair[0], pair[1]]),
+                    _ => u16::from_be_bytes([pair[0], pair[1]]),
+                })
+                .collect();
+            String::from_utf16(&units).ok()?
+        }
+        Encoding::Latin1 => bytes.iter().map(|&b| char::from(b)).collect(),
+        Encoding::Windows1252 => bytes
+            .iter()
+            .map(|&b| match b {
+                0x80..0xA0 => WINDOWS_1252_HIGH[usize::from(b - 0x80)],
+                _ => char::from(b),
+            })
+            .collect(),
+    };
+
+    Some(Decoded {
+        text,
+        encoding,
+        bom,
+    })
+}
@@ -0,0 +1,154 @@
+use std::io;
+use std::path::PathBuf;
+
+use thiserror::Error;
+
+use crate::encoding::Encoding;
+use crate::verify::Mismatch;
+
+/// Errors returned by sleek.
+///
+/// New variants may be added in future releases, so match with a wildcard arm.
+#[derive(Error, Debug)]
+#[non_exhaustive]
+pub enum Error {
+    #[error("Failed to read from stdin: {0}")]
+    Io(#[from] io::Error),
+    #[error("Failed to read glob pattern: {0}")]
+    Glob(#[source] Source),
+    #[error("Failed to read glob pattern: {0}")]
+    Pattern(#[source] Source),
+    #[error("Failed to walk directory: {0}")]
+    Walk(#[source] Source),
+    #[error("Invalid exclude pattern: {0}")]
+    Exclude(#[source] Source),
+    #[error(
+        "Input is not formatted correctly. Run sleek without --check, --diff or --list-different to format it."
+    )]
+    Check,
+    #[error(
+        "{count} {} not formatted correctly. Run sleek without --check, --diff or --list-different to format {}.",
+        if *.count == 1 { "file is" } else { "files are" },
+        if *.count == 1 { "it" } else { "them" }
+    )]
+    CheckFiles { count: usize },
+    #[error(
+        "{count} {} could not be formatted",
+        if *.count == 1 { "file" } else { "files" }
+    )]
+    FailedFiles { count: usize },
+    #[error("Failed to append a trailing newline to the formatted SQL.")]
+    Format(#[from] std::fmt::Error),
+    #[error("Failed to read config file {}: {source}", .path.display())]
+    ConfigRead { path: PathBuf, source: io::Error },
+    #[error("Failed to parse config file {}: {source}", .path.display())]
+    ConfigParse { path: PathBuf, source: Source },
+    #[error("Failed to read {}: {source}", .path.display())]
+    Read { path: PathBuf, source: io::Error },
+    #[error("Failed to write {}: {source}", .path.display())]
+    Write { path: PathBuf, source: io::Error },
+    #[error(
+        "Failed to decode {} as {encoding}. Use --encoding to read it with another encoding.",
+        .path.display()
+    )]
+    Decode { path: PathBuf, encoding: Encoding },
+    #[error(
+        "Failed to encode {} as {encoding}: the formatted text has characters it cannot represent",
+        .path.display()
+    )]
+    Encode { path: PathBuf, encoding: Encoding },
+    #[error(
+        "Refusing to format {}: the formatted SQL differs from the original at {mismatch}. Use --no-verify to skip this check.",
+        .path.display()
+    )]
+    Verify { path: PathBuf, mismatch: Mismatch },
+    #[error(
+        "{} does not reach a stable format after {passes} passes; formatting it again changes:\n{diff}",
+        .path.display()
+    )]
+    Unstable {
+        path: PathBuf,
+        passes: usize,
+        diff: String,
+    },
+    #[error("Failed to access cache {}: {source}", .path.display())]
+    Cache { path: PathBuf, source: io::Error },
+    #[error("Failed to watch files: {0}")]
+    Watch(#[source] Source),
+    #[error("Stopped receiving file change events")]
+    WatchStopped,
+    #[error("Failed to run git {args}: {message}")]
+    Git { args: String, message: String },
+    #[error("A git hook already exists at {}. Use --force to replace it.", .0.display())]
+    HookExists(PathBuf),
+    #[error("Config file {} has no [tool.sleek] table", .0.display())]
+    ConfigMissingTable(PathBuf),
+    #[error("Range formatting is only supported for SQL input")]
+    RangeUnsupported,
+    #[error("Language server protocol error: {0}")]
+    Lsp(#[source] Source),
+    #[error("Language server client disconnected")]
+    LspDisconnected,
+    #[error("Invalid JSON: {0}")]
+    Json(#[source] Source),
+}
+
+/// The error of a dependency behind an [`Error`], boxed so that upgrading the
+/// dependency does not change sleek's API.
+pub(crate) type Source = Box<dyn std::error::Error + Send + Sync>;
+
+/// Boxes the errors of the dependencies of the CLI as the source of a variant.
+#[cfg(feature = "cli")]
+macro_rules! from_cli_errors {
+    ($($error:ty => $variant:ident),* $(,)?) => {
+        $(
+            impl From<$error> for Error {
+                fn from(error: $error) -> Self {
+                    Error::$variant(Box::new(error))
+                }
+            }
+        )*
+    };
+}
+
+#[cfg(feature = "cli")]
+from_cli_errors! {
+    glob::GlobError => Glob,
+    glob::PatternError => Pattern,
+    ignore::Error => Walk,
+    globset::Error => Exclude,
+    notify::Error => Watch,
+    lsp_server::ProtocolError => Lsp,
+    serde_json::Error => Json,
+}
+
+impl Error {
+    /// A short, stable name for the kind of error, as used in reports.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Error::Io(_) => "io",
+            Error::Glob(_) | Error::Pattern(_) => "glob",
+            Error::Walk(_) => "walk",
+            Error::Exclude(_) => "exclude",
+            Error::Check | Error::CheckFiles { .. } => "check",
+            Error::FailedFiles { .. } => "failed-files",
+            Error::Format(_) => "format",
+            Error::ConfigRead { .. } | Error::ConfigParse { .. } | Error::ConfigMissingTable(_) => {
+                "config"
+            }
+            Error::Read { .. } => "read",
+            Error::Write { .. } => "write",
+            Error::Decode { .. } => "decode",
+            Error::Encode { .. } => "encode",
+            Error::Verify { .. } => "verify",
+            Error::Unstable { .. } => "unstable",
+            Error::Cache { .. } => "cache",
+            Error::Watch(_) | Error::WatchStopped => "watch",
+            Error::Git { .. } => "git",
+            Error::HookExists(_) => "hook",
+            Error::RangeUnsupported => "range-unsupported",
+            Error::Lsp(_) | Error::LspDisconnected => "lsp",
+            Error::Json(_) => "json",
+        }
+    }
+}
@@ -0,0 +1,225 @@
+use std::collections::HashSet;
+use std::fs::{self, File, Metadata, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use glob::glob;
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use ignore::WalkBuilder;
+use tempfile::Builder;
+
+use crate::Error;
+
+/// Extensions formatted when walking a directory, unless overridden.
+pub const DEFAULT_EXTENSIONS: &[&str] = &["sql"];
+
+/// Ignore file read alongside `.gitignore` and `.ignore` when walking directories.
+pub const IGNORE_FILE_NAME: &str = ".sleekignore";
+
+/// Expands command line arguments into the list of files to format.
+///
+/// Each argument is expanded as a glob pattern. Matching files are used as-is,
+/// while matching directories are walked recursively for files with one of the
+/// configured extensions, honouring `.gitignore`, `.ignore` and `.sleekignore`.
+/// Paths matching an `--exclude` pattern are skipped either way.
+///
+/// The files are sorted by path, and a file matched more than once, even through
+/// a different path or a symlink, is only listed once.
+pub struct FileFinder {
+    extensions: Vec<String>,
+    exclude: GlobSet,
+}
+
+impl FileFinder {
+    pub fn new(extensions: &[String], exclude: &[String]) -> Result<Self, Error> {
+        let mut builder = GlobSetBuilder::new();
+        for pattern in exclude {
+            builder.add(Glob::new(pattern)?);
+        }
+
+        let extensions = match extensions.is_empty() {
+            true => DEFAULT_EXTENSIONS
+                .iter()
+                .map(|ext| ext.to_string())
+                .collect(),
+            false => extensions
+                .iter()
+                .map(|ext| ext.trim_start_matches('.').to_string())
+                .collect(),
+        };
+
+        Ok(Self {
+            extensions,
+            exclude: builder.build()?,
+        })
+    }
+
+    pub fn find(&self, file_paths: &[String]) -> Result<Vec<PathBuf>, Error> {
+        let mut files = Vec::new();
+
+        for file_path in file_paths {
+            for entry in glob(file_path)? {
+                let path = entry?;
+                if self.is_excluded(&path) {
+                    continue;
+                }
+
+                match path.is_dir() {
+                    true => self.walk(&path, &mut files)?,
+                    false => files.push(path),
+                }
+            }
+        }
+
+        files.sort();
+        let mut seen = HashSet::new();
+        files.retain(|path| seen.insert(fs::canonicalize(path).unwrap_or_else(|_| path.clone())));
+        Ok(files)
+    }
+
+    /// Returns the paths to watch for files matching `file_paths`: each
+    /// argument up to its first component with a glob pattern.
+    pub fn roots(&self, file_paths: &[String]) -> Vec<PathBuf> {
+        let mut roots: Vec<PathBuf> = file_paths
+            .iter()
+            .map(|file_path| {
+                let root: PathBuf = Path::new(file_path)
+                    .components()
+                    .take_while(|component| {
+                        !component
+                            .as_os_str()
+                            .to_string_lossy()
+                            .contains(['*', '?', '[', '{'])
+                    })
+                    .collect();
+                match root.as_os_str().is_empty() {
+                    true => PathBuf::from("."),
+                    false => root,
+                }
+            })
+            .collect();
+        roots.sort();
+        roots.dedup();
+        roots
+    }
+
+    fn walk(&self, dir: &Path, files: &mut Vec<PathBuf>) -> Result<(), Error> {
+        let walker = WalkBuilder::new(dir)
+            .add_custom_ignore_filename(IGNORE_FILE_NAME)
+            .require_git(false)
+            .sort_by_file_name(|a, b| a.cmp(b))
+            .filter_entry({
+                let exclude = self.exclude.clone();
+                move |entry| !matches_any(&exclude, entry.path())
+            })
+            .build();
+
+        for entry in walker {
+            let entry = entry?;
+            let is_file = entry
+                .file_type()
+                .is_some_and(|file_type| file_type.is_file());
+            if is_file && self.has_extension(entry.path()) {
+                files.push(entry.into_path());
+            }
+        }
+
+        Ok(())
+    }
+
+    fn has_extension(&self, path: &Path) -> bool {
+        path.extension()
+            .and_then(|ext| ext.to_str())
+            .is_some_and(|ext| self.extensions.iter().any(|allowed| allowed == ext))
+    }
+
+    fn is_excluded(&self, path: &Path) -> bool {
+        matches_any(&self.exclude, path)
+            || without_current_dir(path)
+                .ancestors()
+                .skip(1)
+                .any(|ancestor| self.exclude.is_match(ancestor))
+    }
+}
+
+/// Matches `path` against `set`, either as a whole or by its final component.
+fn matches_any(set: &GlobSet, path: &Path) -> bool {
+    set.is_match(without_current_dir(path))
+        || path.file_name().is_some_and(|name| set.is_match(name))
+}
+
+/// Strips a leading `./`, so `vendor/**` matches `./vendor/a.sql` as it does
+/// `vendor/a.sql`.
+fn without_current_dir(path: &Path) -> &Path {
+    path.strip_prefix(".").unwrap_or(path)
+}
+
+/// Replaces the contents of the file at `path` without ever leaving it partly
+/// written.
+///
+/// The contents go to a temporary file in the same directory, which is synced
+/// to disk and renamed over the original. The original's permissions and, where
+/// possible, its owner are kept. A symlink is left in place and the file it
+/// points to is replaced instead.
+pub fn write_atomic(path: &Path, contents: &[u8]) -> Result<(), Error> {
+    let write_error = |source| Error::Write {
+        path: path.to_path_buf(),
+        source,
+    };
+
+    let target = fs::canonicalize(path).map_err(write_error)?;
+    let metadata = fs::metadata(&target).map_err(write_error)?;
+    // Renaming only needs access to the directory, so check the file itself
+    if metadata.permissions().readonly() {
+        let source = io::Error::new(io::ErrorKind::PermissionDenied, "file is read-only");
+        return Err(write_error(source));
+    }
+    OpenOptions::new()
+        .write(true)
+        .open(&target)
+        .map_err(write_error)?;
+
+    let dir = target.parent().unwrap_or(Path::new("."));
+    let name = target.file_name().unwrap_or_default().to_string_lossy();
+    let mut temp = Builder::new()
+        .prefix(&format!(".{name}."))
+        .suffix(".tmp")
+        .tempfile_in(dir)
+        .map_err(write_error)?;
+
+    temp.write_all(contents).map_err(write_error)?;
+    temp.as_file()
+        .set_permissions(metadata.permissions())
+        .map_err(write_error)?;
+    preserve_owner(temp.as_file(), &metadata);
+    temp.as_file().sync_all().map_err(write_error)?;
+    temp.persist(&target).map_err(|e| write_error(e.error))?;
+    sync_dir(dir);
+
+    Ok(())
+}
+
+#[cfg(unix)]
+fn preserve_owner(file: &File, metadata: &Metadata) {
+    use std::os::unix::fs::{MetadataExt, fchown};
+
+    // Only root can give a file away, but the group can often still be kept
+    if fchown(file, Some(metadata.uid()), Some(metadata.gid())).is_err() {
+        let _ = fchown(file, None, Some(metadata.gid()));
+    }
+}
+
+#[cfg(not(unix))]
+fn preserve_owner(_file: &File, _metadata: &Metadata) {}
+
+/// Makes a rename in `dir` durable. Failures are ignored, as the rename itself
+/// has already succeeded.
+#[cfg(unix)]
+fn sync_dir(dir: &Path) {
+    if let Ok(dir) = File::open(dir) {
+        let _ = dir.sync_all();
+    }
+}
+
+#[cfg(not(unix))]
+fn sync_dir(_dir: &Path) {}
@@ -0,0 +1,248 @@
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::ops::RangeInclusive;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use crate::Error;
+
+/// Returns the files of the repository in the current directory that differ
+/// from `rev`, including uncommitted and untracked files. Deleted files are
+/// left out, and paths are canonical.
+pub fn changed_since(rev: &str) -> Result<HashSet<PathBuf>, Error> {
+    let root = root()?;
+    let changed = git(&[
+        "diff",
+        "--name-only",
+        "-z",
+        "--diff-filter=d",
+        "--end-of-options",
+        rev,
+        "--",
+    ])?;
+    let untracked = git(&[
+        "ls-files",
+        "--others",
+        "--exclude-standard",
+        "--full-name",
+        "-z",
+        "--",
+        ":/",
+    ])?;
+    Ok(paths(&root, &[changed, untracked]))
+}
+
+/// Returns the files of the repository in the current directory with staged
+/// changes, leaving out deleted files. Paths are canonical.
+pub fn staged() -> Result<HashSet<PathBuf>, Error> {
+    let root = root()?;
+    let staged = git(&["diff", "--cached", "--name-only", "-z", "--diff-filter=d"])?;
+    Ok(paths(&root, &[staged]))
+}
+
+/// Returns the contents of `path` as staged in the index of the repository in
+/// the current directory.
+pub fn staged_contents(path: &Path) -> Result<Vec<u8>, Error> {
+    let canonicalize = |path: &Path| {
+        fs::canonicalize(path).map_err(|source| Error::Read {
+            path: path.to_path_buf(),
+            source,
+        })
+    };
+    let root = canonicalize(&root()?)?;
+    let path = canonicalize(path)?;
+    // Index paths are relative to the top-level directory, separated by `/`
+    let name = path
+        .strip_prefix(&root)
+        .unwrap_or(&path)
+        .iter()
+        .map(|part| part.to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/");
+    git_bytes(&["cat-file", "blob", &format!(":{name}")])
+}
+
+/// The lines of each file in a repository that differ from a revision, as
+/// found by [`changed_lines`].
+pub struct ChangedLines {
+    files: HashMap<PathBuf, Vec<RangeInclusive<usize>>>,
+    untracked: HashSet<PathBuf>,
+}
+
+impl ChangedLines {
+    /// Returns the 1-based, inclusive ranges of lines of `path` as it is now
+    /// that differ from the revision, or `None` if the file is untracked, so
+    /// every line is new. Lines only removed are reported as the line before
+    /// them.
+    pub fn get(&self, path: &Path) -> Option<&[RangeInclusive<usize>]> {
+        let Ok(path) = fs::canonicalize(path) else {
+            return Some(&[]);
+        };
+        if self.untracked.contains(&path) {
+            return None;
+        }
+        Some(self.files.get(&path).map_or(&[], Vec::as_slice))
+    }
+}
+
+/// Returns the lines of every file of the repository in the current directory
+/// that differ from `rev`, from a single diff of the whole work tree.
+pub fn changed_lines(rev: &str) -> Result<ChangedLines, Error> {
+    let root = root()?;
+    let diff = git(&[
+        "diff",
+        "--unified=0",
+        "--no-color",
+        "--no-ext-diff",
+        "--no-relative",
+        "--src-prefix=a/",
+        "--dst-prefix=b/",
+        "--end-of-options",
+        rev,
+        "--",
+    ])?;
+    let untracked = git(&[
+        "ls-files",
+        "--others",
+        "--exclude-standard",
+        "--full-name",
+        "-z",
+        "--",
+        ":/",
+    ])?;
+
+    // Headers run from `diff --git` to the first hunk, so an added line
+    // starting with `++` is not taken for the `+++ b/<path>` header
+    let mut files: HashMap<PathBuf, Vec<RangeInclusive<usize>>> = HashMap::new();
+    let mut path = None;
+    let mut in_header = false;
+    for line in diff.lines() {
+        if line.starts_with("diff --git ") {
+            path = None;
+            in_header = true;
+        } else if in_header && line.starts_with("@@ ") {
+            in_header = false;
+        } else if in_header {
+            if let Some(name) = line.strip_prefix("+++ ") {
+                // Deleted files have no new name and no lines left
+                path = diff_path(name).and_then(|name| fs::canonicalize(root.join(name)).ok());
+            }
+            continue;
+        }
+
+        if let (Some(path), Some(lines)) = (&path, hunk_lines(line)) {
+            files.entry(path.clone()).or_default().push(lines);
+        }
+    }
+
+    Ok(ChangedLines {
+        files,
+        untracked: paths(&root, &[untracked]),
+    })
+}
+
+/// Reads the path of the new file from the name in a `+++ b/<path>` header,
+/// which git quotes like a C string if it holds unusual characters and ends
+/// with a tab if it holds spaces.
+fn diff_path(name: &str) -> Option<String> {
+    let name = name.strip_suffix('\t').unwrap_or(name);
+    let Some(quoted) = name.strip_prefix('"') else {
+        return name.strip_prefix("b/").map(str::to_string);
+    };
+
+    let mut bytes = Vec::new();
+    let mut chars = quoted.strip_suffix('"')?.bytes();
+    while let Some(byte) = chars.next() {
+        if byte != b'\\' {
+            bytes.push(byte);
+            continue;
+        }
+        bytes.push(match chars.next()? {
+            b'a' => 0x07,
+            b'b' => 0x08,
+            b't' => b'\t',
+            b'n' => b'\n',
+            b'v' => 0x0b,
+            b'f' => 0x0c,
+            b'r' => b'\r',
+            // Other bytes are written as three octal digits
+            digit @ b'0'..=b'3' => {
+                let mut value = digit - b'0';
+                for _ in 0..2 {
+                    value = value * 8 + chars.next().filter(|d| (b'0'..=b'7').contains(d))? - b'0';
+                }
+                value
+            }
+            other => other,
+        });
+    }
+    let name = String::from_utf8(bytes).ok()?;
+    name.strip_prefix("b/").map(str::to_string)
+}
+
+/// Reads the lines of the new file from a hunk header such as
+/// `@@ -3,2 +4,5 @@`.
+fn hunk_lines(line: &str) -> Option<RangeInclusive<usize>> {
+    let new = line
+        .strip_prefix("@@ -")?
+        .split(' ')
+        .nth(1)?
+        .strip_prefix('+')?;
+    let (start, count) = match new.split_once(',') {
+        Some((start, count)) => (start.parse::<usize>().ok()?, count.parse::<usize>().ok()?),
+        None => (new.parse().ok()?, 1),
+    };
+    match count {
+        0 => Some(start.max(1)..=start.max(1)),
+        count => Some(start..=start + count - 1),
+    }
+}
+
+/// Returns where git looks for the hook `name` of the repository in the current
+/// directory, honouring `core.hooksPath`.
+pub fn hook_path(name: &str) -> Result<PathBuf, Error> {
+    let path = git(&["rev-parse", "--git-path", &format!("hooks/{name}")])?;
+    Ok(PathBuf::from(path.trim_end_matches(['\r', '\n'])))
+}
+
+/// Returns the top-level directory of the repository in the current directory.
+fn root() -> Result<PathBuf, Error> {
+    let root = git(&["rev-parse", "--show-toplevel"])?;
+    Ok(PathBuf::from(root.trim_end_matches(['\r', '\n'])))
+}
+
+/// Resolves NUL-separated paths relative to `root`, skipping any that no
+/// longer exist.
+fn paths(root: &Path, lists: &[String]) -> HashSet<PathBuf> {
+    lists
+        .iter()
+        .flat_map(|list| list.split('\0'))
+        .filter(|name| !name.is_empty())
+        .filter_map(|name| fs::canonicalize(root.join(name)).ok())
+        .collect()
+}
+
+/// Runs git with `args` in the current directory and returns its output.
+fn git(args: &[&str]) -> Result<String, Error> {
+    let output = git_bytes(args)?;
+    Ok(String::from_utf8_lossy(&output).into_owned())
+}
+
+/// Runs git with `args` in the current directory and returns its output as
+/// bytes, for contents that may not be UTF-8.
+fn git_bytes(args: &[&str]) -> Result<Vec<u8>, Error> {
+    let error = |message: String| Error::Git {
+        args: args.join(" "),
+        message,
+    };
+
+    let output = Command::new("git")
+        .args(args)
+        .output()
+        .map_err(|e| error(e.to_string()))?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(error(stderr.trim().to_string()));
+    }
+    Ok(output.stdout)
+}
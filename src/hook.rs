@@ -0,0 +1,77 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::{Error, git};
+
+/// Pre-commit hook that fails the commit if staged SQL files are not formatted,
+/// checking the contents that would be committed.
+pub const CHECK_HOOK: &str = "#!/bin/sh
+# Installed by `sleek install-hook`: fails the commit if staged SQL files are
+# not formatted.
+exec sleek --check --staged .
+";
+
+/// Pre-commit hook that formats staged SQL files and stages the result,
+/// refusing files that also have unstaged changes.
+pub const FIX_HOOK: &str = "#!/bin/sh
+# Installed by `sleek install-hook --fix`: formats the staged SQL files and
+# stages the result.
+if [ -z \"$(git diff --cached --name-only --diff-filter=ACMR -- '*.sql')\" ]; then
+    exit 0
+fi
+# Staging a file with unstaged changes would commit those changes too
+partly_staged=$(git -c core.quotePath=false diff --name-only -- '*.sql' | while IFS= read -r file; do
+    git diff --cached --quiet -- \":(literal)$file\" || printf '%s\\n' \"$file\"
+done)
+if [ -n \"$partly_staged\" ]; then
+    echo 'sleek: not formatting files with unstaged changes; stage or stash them first:' >&2
+    printf '%s\\n' \"$partly_staged\" >&2
+    exit 1
+fi
+sleek --staged . || exit 1
+git diff --cached --name-only -z --diff-filter=ACMR -- '*.sql' | xargs -0 git add --
+";
+
+/// Writes the `pre-commit` hook of the git repository in the current
+/// directory, checking the staged SQL files or, with `fix`, formatting and
+/// staging them. An existing hook is only replaced with `force`.
+///
+/// Returns the path of the hook.
+pub fn install(fix: bool, force: bool) -> Result<PathBuf, Error> {
+    let path = git::hook_path("pre-commit")?;
+    if !force && path.symlink_metadata().is_ok() {
+        return Err(Error::HookExists(path));
+    }
+
+    let script = match fix {
+        true => FIX_HOOK,
+        false => CHECK_HOOK,
+    };
+    let write = || {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(&path, script)?;
+        make_executable(&path)
+    };
+    write().map_err(|source| Error::Write {
+        path: path.clone(),
+        source,
+    })?;
+    Ok(path)
+}
+
+#[cfg(unix)]
+fn make_executable(path: &Path) -> io::Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+
+    let mut permissions = fs::metadata(path)?.permissions();
+    permissions.set_mode(permissions.mode() | 0o755);
+    fs::set_permissions(path, permissions)
+}
+
+#[cfg(not(unix))]
+fn make_executable(_path: &Path) -> io::Result<()> {
+    Ok(())
+}
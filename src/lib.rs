@@ -0,0 +1,281 @@
+//! Sleek formats SQL.
+//!
+//! This crate is the library behind the `sleek` command line tool, so formatting
+//! through it produces the same output as the CLI with the same settings. The
+//! CLI also refuses output whose tokens differ from the input, which
+//! [`verify::verify`] checks; `format_str` returns such output as it is.
+//!
+//! ```
+//! let config = sleek::Config::default();
+//!
+//! let formatted = sleek::format_str("select * from users", &config).unwrap();
+//! assert_eq!(formatted, "SELECT\n    *\nFROM\n    users\n");
+//!
+//! assert!(sleek::check_str(&formatted, &config).unwrap());
+//! ```
+
+// Modules behind the `cli` feature serve the binary and are not part of the
+// stable API
+#[cfg(feature = "cli")]
+#[doc(hidden)]
+pub mod cache;
+pub mod config;
+mod dialect;
+pub mod diff;
+mod directives;
+pub mod encoding;
+mod error;
+#[cfg(feature = "cli")]
+#[doc(hidden)]
+pub mod files;
+#[cfg(feature = "cli")]
+#[doc(hidden)]
+pub mod git;
+#[cfg(feature = "cli")]
+#[doc(hidden)]
+pub mod hook;
+#[cfg(feature = "cli")]
+#[doc(hidden)]
+pub mod lsp;
+pub mod markdown;
+#[cfg(feature = "cli")]
+#[doc(hidden)]
+pub mod report;
+mod routines;
+mod statements;
+pub mod verify;
+#[cfg(feature = "cli")]
+#[doc(hidden)]
+pub mod watch;
+
+use std::ops::Range;
+use std::path::Path;
+
+use clap::ValueEnum;
+use sqlformat::{QueryParams, format};
+
+pub use config::Config;
+use config::EndOfLine;
+pub use dialect::Dialect;
+use directives::Segment;
+pub use error::Error;
+
+/// The kind of document being formatted.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, ValueEnum)]
+pub enum Language {
+    /// SQL source
+    #[default]
+    Sql,
+    /// Markdown, formatting each SQL fenced code block
+    Markdown,
+}
+
+impl Language {
+    /// Detects the language of a file from its extension, defaulting to SQL.
+    pub fn from_path(path: &Path) -> Self {
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some("md" | "markdown") => Language::Markdown,
+            _ => Language::Sql,
+        }
+    }
+}
+
+/// Formats `input` according to `config`.
+///
+/// Regions protected by `-- sleek:` comment directives are passed through
+/// unchanged, and a trailing newline is added if `config.trailing_newline` is set.
+/// Every line ends with the style chosen by `config.end_of_line`.
+pub fn format_str(input: &str, config: &Config) -> Result<String, Error> {
+    if directives::is_ignored_file(input) {
+        return Ok(input.to_string());
+    }
+
+    let end_of_line = config.end_of_line.resolve(input);
+    let input = &input.replace("\r\n", "\n");
+    let segments = directives::segments(input, config.dialect);
+
+    let mut formatted = if segments.iter().all(|s| matches!(s, Segment::Format(_))) {
+        format_sql(input, config)
+    } else {
+        // Protected regions are kept apart from the surrounding queries like any
+        // other query, so the output is stable when formatted again.
+        let separator = "\n".repeat(config.lines_between_queries.max(1).into());
+        segments
+            .iter()
+            .map(|segment| match segment {
+                Segment::Format(text) => format_sql(text, config).trim().to_string(),
+                Segment::Delimited { text, delimiter } => {
+                    directives::split_delimited(text, delimiter, config.dialect)
+                        .into_iter()
+                        .map(|(statement, terminated)| {
+                            let formatted = format_sql(statement, config);
+                            match terminated {
+                                true => format!("{} {delimiter}", formatted.trim()),
+                                false => formatted.trim().to_string(),
+                            }
+                        })
+                        .collect::<Vec<_>>()
+                        .join(&separator)
+                }
+                Segment::Verbatim(text) => text.trim_end_matches(['\r', '\n']).to_string(),
+            })
+            .collect::<Vec<_>>()
+            .join(&separator)
+    };
+
+    if config.trailing_newline && !formatted.ends_with('\n') {
+        formatted.push('\n');
+    }
+    if end_of_line != "\n" {
+        formatted = formatted.replace('\n', end_of_line);
+    }
+
+    Ok(formatted)
+}
+
+/// Formats SQL free of directives, reading it as `config.dialect` if one is set.
+fn format_sql(sql: &str, config: &Config) -> String {
+    let masked = dialect::mask(sql, config.dialect, config.uppercase);
+    let formatted = format(
+        &masked.text,
+        &QueryParams::default(),
+        &config.format_options(),
+    );
+    masked.unmask(&formatted, config)
+}
+
+/// Returns true if `input` is already formatted according to `config`.
+pub fn check_str(input: &str, config: &Config) -> Result<bool, Error> {
+    Ok(format_str(input, config)? == input)
+}
+
+/// A replacement for a byte range of the original input.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Edit {
+    /// Byte range of the input that is replaced.
+    pub range: Range<usize>,
+    /// Text that replaces it.
+    pub text: String,
+}
+
+/// Formats only the statements overlapping `range`, a byte range into `input`.
+///
+/// An empty range selects the statement containing that position. Everything
+/// outside the returned edit's range is left untouched, and no trailing newline
+/// is added. The edit keeps the line endings of `input` unless `config` sets
+/// them. Returns `None` if no statement overlaps the range.
+///
+/// Statements protected by `-- sleek:` directives are never selected, and
+/// anything between the selected statements that is not formatted, such as a
+/// protected statement or a `GO` line, is kept as written.
+pub fn format_range(
+    input: &str,
+    range: Range<usize>,
+    config: &Config,
+) -> Result<Option<Edit>, Error> {
+    if directives::is_ignored_file(input) {
+        return Ok(None);
+    }
+
+    let config = keep_line_endings(input, config);
+    let statements = directives::statements(input, config.dialect);
+    let overlapping: Vec<_> = statements
+        .iter()
+        .filter(|statement| overlaps(&statement.range, &range))
+        .collect();
+    let (Some(first), Some(last)) = (overlapping.first(), overlapping.last()) else {
+        return Ok(None);
+    };
+
+    // Statements of a batch are formatted together, keeping whatever separates
+    // the batches as written
+    let mut text = String::new();
+    let mut pos = first.range.start;
+    let batches = overlapping.chunk_by(|a, b| a.segment == b.segment && a.delimiter.is_none());
+    for batch in batches {
+        let range = batch[0].range.start..batch[batch.len() - 1].range.end;
+        let edit = format_span(input, range, batch[0].delimiter, &config)?;
+        text.push_str(&input[pos..edit.range.start]);
+        text.push_str(&edit.text);
+        pos = edit.range.end;
+    }
+
+    Ok(Some(Edit {
+        range: first.range.start..last.range.end,
+        text,
+    }))
+}
+
+/// Formats only the statements overlapping any of `ranges`, byte ranges into
+/// `input`, each on its own.
+///
+/// Returns an edit for each of those statements, in order. The text between
+/// statements and every other statement are left untouched, and formatted
+/// statements keep the line endings of `input` unless `config` sets them. As
+/// with [`format_range`], an empty range selects the statement containing that
+/// position. Statements protected by `-- sleek:` directives are never selected.
+pub fn format_ranges(
+    input: &str,
+    ranges: &[Range<usize>],
+    config: &Config,
+) -> Result<Vec<Edit>, Error> {
+    if directives::is_ignored_file(input) {
+        return Ok(Vec::new());
+    }
+
+    let config = keep_line_endings(input, config);
+    directives::statements(input, config.dialect)
+        .into_iter()
+        .filter(|statement| ranges.iter().any(|range| overlaps(&statement.range, range)))
+        .map(|statement| format_span(input, statement.range, statement.delimiter, &config))
+        .collect()
+}
+
+/// Returns `config` with its line endings resolved from the whole of `input`,
+/// so statements formatted on their own end their lines like the rest of it.
+fn keep_line_endings(input: &str, config: &Config) -> Config {
+    let mut config = config.clone();
+    config.end_of_line = match config.end_of_line.resolve(input) {
+        "\r\n" => EndOfLine::Crlf,
+        _ => EndOfLine::Lf,
+    };
+    config
+}
+
+/// Returns true if the statement at `span` is selected by `range`.
+fn overlaps(span: &Range<usize>, range: &Range<usize>) -> bool {
+    match range.is_empty() {
+        true => span.start <= range.start && range.start <= span.end,
+        false => span.start < range.end && range.start < span.end,
+    }
+}
+
+/// Formats the statements at `span` of `input`, without a trailing newline.
+/// A statement ending in a `DELIMITER` other than `;` is formatted on its own.
+fn format_span(
+    input: &str,
+    span: Range<usize>,
+    delimiter: Option<&str>,
+    config: &Config,
+) -> Result<Edit, Error> {
+    let mut config = config.clone();
+    config.trailing_newline = false;
+    let text = &input[span.clone()];
+    let formatted =
+        match delimiter.and_then(|delimiter| Some((text.strip_suffix(delimiter)?, delimiter))) {
+            Some((statement, delimiter)) => {
+                format!("{} {delimiter}", format_str(statement, &config)?.trim())
+            }
+            None => format_str(text, &config)?,
+        };
+
+    // Keep the statements at the indentation of the line they start on
+    let line_start = input[..span.start].rfind('\n').map_or(0, |i| i + 1);
+    let indent = &input[line_start..span.start];
+    let text = match indent.trim().is_empty() && !indent.is_empty() {
+        true => formatted.replace('\n', &format!("\n{indent}")),
+        false => formatted,
+    };
+
+    Ok(Edit { range: span, text })
+}
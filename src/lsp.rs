@@ -0,0 +1,399 @@
+use std::collections::HashMap;
+use std::env;
+use std::path::Path;
+
+use lsp_server::{Connection, ErrorCode, Message, Notification, Request, Response};
+use lsp_types::notification::{
+    DidChangeTextDocument, DidCloseTextDocument, DidOpenTextDocument,
+    Notification as NotificationTrait, PublishDiagnostics,
+};
+use lsp_types::request::{Formatting, OnTypeFormatting, RangeFormatting, Request as RequestTrait};
+use lsp_types::{
+    Diagnostic, DiagnosticSeverity, DidChangeTextDocumentParams, DidCloseTextDocumentParams,
+    DidOpenTextDocumentParams, DocumentFormattingParams, DocumentOnTypeFormattingOptions,
+    DocumentOnTypeFormattingParams, DocumentRangeFormattingParams, OneOf, Position,
+    PublishDiagnosticsParams, Range, ServerCapabilities, TextDocumentSyncCapability,
+    TextDocumentSyncKind, TextEdit, Url,
+};
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+use similar::TextDiff;
+
+use crate::config::{Config, ConfigResolver};
+use crate::markdown::format_markdown;
+use crate::{Error, Language, format_range, format_str, verify};
+
+/// Character that triggers on-type formatting of the statement it terminates.
+const ON_TYPE_TRIGGER: &str = ";";
+
+/// Runs a language server over stdin and stdout until the client exits.
+///
+/// Settings for each document are resolved through `configs`, so editors get
+/// the same config file discovery as the command line.
+pub fn run(configs: ConfigResolver) -> Result<(), Error> {
+    let (connection, io_threads) = Connection::stdio();
+    serve(&connection, configs)?;
+    // The writer thread only stops once the connection is dropped
+    drop(connection);
+    io_threads.join()?;
+    Ok(())
+}
+
+/// Runs a language server on `connection` until the client exits.
+pub fn serve(connection: &Connection, configs: ConfigResolver) -> Result<(), Error> {
+    let capabilities = ServerCapabilities {
+        text_document_sync: Some(TextDocumentSyncCapability::Kind(TextDocumentSyncKind::FULL)),
+        document_formatting_provider: Some(OneOf::Left(true)),
+        document_range_formatting_provider: Some(OneOf::Left(true)),
+        document_on_type_formatting_provider: Some(DocumentOnTypeFormattingOptions {
+            first_trigger_character: ON_TYPE_TRIGGER.to_string(),
+            more_trigger_character: None,
+        }),
+        ..ServerCapabilities::default()
+    };
+    connection.initialize(serde_json::to_value(capabilities)?)?;
+
+    let mut server = Server {
+        connection,
+        configs,
+        documents: HashMap::new(),
+    };
+
+    for message in &connection.receiver {
+        match message {
+            Message::Request(request) => {
+                if connection.handle_shutdown(&request)? {
+                    return Ok(());
+                }
+                server.handle_request(request)?;
+            }
+            Message::Notification(notification) => server.handle_notification(notification)?,
+            Message::Response(_) => {}
+        }
+    }
+
+    Ok(())
+}
+
+struct Server<'a> {
+    connection: &'a Connection,
+    /// Used as a template so config files are re-read for every request.
+    configs: ConfigResolver,
+    documents: HashMap<Url, String>,
+}
+
+impl Server<'_> {
+    fn handle_request(&self, request: Request) -> Result<(), Error> {
+        let id = request.id.clone();
+        let result = match request.method.as_str() {
+            Formatting::METHOD => params(request).map(|params| self.format_document(params)),
+            RangeFormatting::METHOD => {
+                params(request).map(|params| self.format_document_range(params))
+            }
+            OnTypeFormatting::METHOD => params(request).map(|params| self.format_on_type(params)),
+            method => {
+                let message = format!("Unsupported request: {method}");
+                let response = Response::new_err(id, ErrorCode::MethodNotFound as i32, message);
+                return self.send(response.into());
+            }
+        };
+
+        let response = match result {
+            Ok(Ok(edits)) => Response::new_ok(id, edits),
+            Ok(Err(e)) => Response::new_err(id, ErrorCode::InternalError as i32, e.to_string()),
+            Err(e) => Response::new_err(id, ErrorCode::InvalidParams as i32, e.to_string()),
+        };
+        self.send(response.into())
+    }
+
+    fn handle_notification(&mut self, notification: Notification) -> Result<(), Error> {
+        match notification.method.as_str() {
+            DidOpenTextDocument::METHOD => {
+                let Some(params) = notification_params::<DidOpenTextDocumentParams>(notification)
+                else {
+                    return Ok(());
+                };
+                let uri = params.text_document.uri;
+                self.documents
+                    .insert(uri.clone(), params.text_document.text);
+                self.publish_diagnostics(uri)
+            }
+            DidChangeTextDocument::METHOD => {
+                let Some(params) = notification_params::<DidChangeTextDocumentParams>(notification)
+                else {
+                    return Ok(());
+                };
+                let uri = params.text_document.uri;
+                // With full sync, the last change holds the whole document
+                if let Some(change) = params.content_changes.into_iter().last() {
+                    self.documents.insert(uri.clone(), change.text);
+                }
+                self.publish_diagnostics(uri)
+            }
+            DidCloseTextDocument::METHOD => {
+                let Some(params) = notification_params::<DidCloseTextDocumentParams>(notification)
+                else {
+                    return Ok(());
+                };
+                let uri = params.text_document.uri;
+                self.documents.remove(&uri);
+                self.send_diagnostics(uri, Vec::new())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn format_document(
+        &self,
+        params: DocumentFormattingParams,
+    ) -> Result<Option<Vec<TextEdit>>, Error> {
+        let uri = params.text_document.uri;
+        let Some(text) = self.documents.get(&uri) else {
+            return Ok(None);
+        };
+
+        let config = self.config_for(&uri)?;
+        let language = language_of(&uri);
+        let formatted = format(text, language, &config)?;
+        match verified(&uri, text, &formatted, language, &config) {
+            true => Ok(Some(text_edits(text, &formatted))),
+            false => Ok(Some(Vec::new())),
+        }
+    }
+
+    fn format_document_range(
+        &self,
+        params: DocumentRangeFormattingParams,
+    ) -> Result<Option<Vec<TextEdit>>, Error> {
+        let uri = params.text_document.uri;
+        let Some(text) = self.documents.get(&uri) else {
+            return Ok(None);
+        };
+
+        let range = offset_at(text, params.range.start)..offset_at(text, params.range.end);
+        self.format_offsets(&uri, range)
+    }
+
+    fn format_on_type(
+        &self,
+        params: DocumentOnTypeFormattingParams,
+    ) -> Result<Option<Vec<TextEdit>>, Error> {
+        let uri = params.text_document_position.text_document.uri;
+        let Some(text) = self.documents.get(&uri) else {
+            return Ok(None);
+        };
+        // Markdown documents are only formatted as a whole
+        if params.ch != ON_TYPE_TRIGGER || language_of(&uri) != Language::Sql {
+            return Ok(None);
+        }
+
+        let offset = offset_at(text, params.text_document_position.position);
+        self.format_offsets(&uri, offset..offset)
+    }
+
+    fn format_offsets(
+        &self,
+        uri: &Url,
+        range: std::ops::Range<usize>,
+    ) -> Result<Option<Vec<TextEdit>>, Error> {
+        let text = &self.documents[uri];
+        if language_of(uri) != Language::Sql {
+            return Err(Error::RangeUnsupported);
+        }
+        let config = self.config_for(uri)?;
+        let edit = format_range(text, range, &config)?;
+
+        Ok(Some(match edit {
+            Some(edit)
+                if text[edit.range.clone()] != edit.text
+                    && verified(
+                        uri,
+                        &text[edit.range.clone()],
+                        &edit.text,
+                        Language::Sql,
+                        &config,
+                    ) =>
+            {
+                vec![TextEdit {
+                    range: Range::new(
+                        position_at(text, edit.range.start),
+                        position_at(text, edit.range.end),
+                    ),
+                    new_text: edit.text,
+                }]
+            }
+            _ => Vec::new(),
+        }))
+    }
+
+    fn publish_diagnostics(&self, uri: Url) -> Result<(), Error> {
+        let Some(text) = self.documents.get(&uri) else {
+            return Ok(());
+        };
+
+        // A document that cannot be formatted has no unformatted regions to report
+        let language = language_of(&uri);
+        let formatted = self.config_for(&uri).and_then(|config| {
+            let formatted = format(text, language, &config)?;
+            Ok(verified(&uri, text, &formatted, language, &config).then_some(formatted))
+        });
+        let diagnostics = match formatted {
+            Ok(Some(formatted)) => text_edits(text, &formatted)
+                .into_iter()
+                .map(|edit| Diagnostic {
+                    range: edit.range,
+                    severity: Some(DiagnosticSeverity::WARNING),
+                    source: Some("sleek".to_string()),
+                    message: "Code is not formatted correctly".to_string(),
+                    ..Diagnostic::default()
+                })
+                .collect(),
+            Ok(None) | Err(_) => Vec::new(),
+        };
+
+        self.send_diagnostics(uri, diagnostics)
+    }
+
+    fn send_diagnostics(&self, uri: Url, diagnostics: Vec<Diagnostic>) -> Result<(), Error> {
+        let params = PublishDiagnosticsParams::new(uri, diagnostics, None);
+        let notification = Notification::new(PublishDiagnostics::METHOD.to_string(), params);
+        self.send(notification.into())
+    }
+
+    fn config_for(&self, uri: &Url) -> Result<Config, Error> {
+        let path = match uri.to_file_path() {
+            Ok(path) => path,
+            Err(()) => env::current_dir()?,
+        };
+        self.configs.clone().config_for(&path)
+    }
+
+    fn send(&self, message: Message) -> Result<(), Error> {
+        self.connection
+            .sender
+            .send(message)
+            .map_err(|_| Error::LspDisconnected)
+    }
+}
+
+/// Detects the language of a document from the extension of its URI, like the
+/// CLI does for files, so `.md` and `.markdown` documents are Markdown.
+fn language_of(uri: &Url) -> Language {
+    Language::from_path(Path::new(uri.path()))
+}
+
+/// Formats a whole document as `language`.
+fn format(text: &str, language: Language, config: &Config) -> Result<String, Error> {
+    match language {
+        Language::Sql => format_str(text, config),
+        Language::Markdown => Ok(format_markdown(text, config)?.text),
+    }
+}
+
+/// Returns true if formatting `original` as `formatted` only changed
+/// whitespace and keyword case, logging the first difference otherwise. Like
+/// the CLI, the server never offers formatting that fails this check.
+fn verified(
+    uri: &Url,
+    original: &str,
+    formatted: &str,
+    language: Language,
+    config: &Config,
+) -> bool {
+    match verify::verify(original, formatted, language, config.dialect) {
+        Ok(()) => true,
+        Err(mismatch) => {
+            eprintln!(
+                "Not formatting {uri}: the formatted SQL differs from the original at {mismatch}"
+            );
+            false
+        }
+    }
+}
+
+fn params<T: DeserializeOwned>(message: impl Into<Message>) -> Result<T, Error> {
+    let params = match message.into() {
+        Message::Request(request) => request.params,
+        Message::Notification(notification) => notification.params,
+        Message::Response(_) => Value::Null,
+    };
+    Ok(serde_json::from_value(params)?)
+}
+
+/// Reads the params of `notification`, logging and ignoring it if they are
+/// invalid, as there is no response to report the error in.
+fn notification_params<T: DeserializeOwned>(notification: Notification) -> Option<T> {
+    let method = notification.method.clone();
+    params(notification)
+        .inspect_err(|e| eprintln!("Ignoring {method} notification: {e}"))
+        .ok()
+}
+
+/// Computes line-based edits that turn `original` into `formatted`, one per
+/// changed region, so editors can keep cursors and folds outside of them.
+fn text_edits(original: &str, formatted: &str) -> Vec<TextEdit> {
+    let old_lines = line_offsets(original);
+    let new_lines = line_offsets(formatted);
+
+    TextDiff::from_lines(original, formatted)
+        .grouped_ops(0)
+        .iter()
+        .filter_map(|group| {
+            let (first, last) = (group.first()?, group.last()?);
+            let old = old_lines[first.old_range().start]..old_lines[last.old_range().end];
+            let new = new_lines[first.new_range().start]..new_lines[last.new_range().end];
+
+            Some(TextEdit {
+                range: Range::new(
+                    position_at(original, old.start),
+                    position_at(original, old.end),
+                ),
+                new_text: formatted[new].to_string(),
+            })
+        })
+        .collect()
+}
+
+/// Returns the byte offset of the start of each line, followed by the length of `text`.
+fn line_offsets(text: &str) -> Vec<usize> {
+    let mut offsets: Vec<usize> = std::iter::once(0)
+        .chain(text.match_indices('\n').map(|(i, _)| i + 1))
+        .collect();
+    if offsets.last() != Some(&text.len()) {
+        offsets.push(text.len());
+    }
+    offsets
+}
+
+/// Converts a byte offset into an LSP position, counting UTF-16 code units.
+fn position_at(text: &str, offset: usize) -> Position {
+    let before = &text[..offset];
+    let line = before.matches('\n').count();
+    let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+    let character = before[line_start..].encode_utf16().count();
+    Position::new(line as u32, character as u32)
+}
+
+/// Converts an LSP position into a byte offset, clamping it to the document.
+fn offset_at(text: &str, position: Position) -> usize {
+    let mut line_start = 0;
+    for _ in 0..position.line {
+        match text[line_start..].find('\n') {
+            Some(i) => line_start += i + 1,
+            None => return text.len(),
+        }
+    }
+
+    let line_end = text[line_start..]
+        .find('\n')
+        .map_or(text.len(), |i| line_start + i);
+
+    let mut character = 0;
+    for (i, ch) in text[line_start..line_end].char_indices() {
+        if character >= position.character {
+            return line_start + i;
+        }
+        character += ch.len_utf16() as u32;
+    }
+    line_end
+}
@@ -1,11 +1,23 @@
+use std::collections::{HashMap, HashSet};
 use std::io::{Read, Write};
-use std::process;
-use std::{fs, io};
+use std::num::NonZeroUsize;
+use std::ops::{Range, RangeInclusive};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::{env, fs, io, panic, process, thread};
 
-use clap::Parser;
-use glob::glob;
-use sqlformat::{FormatOptions, Indent, QueryParams, format};
-use thiserror::Error;
+use clap::{Parser, Subcommand};
+
+use sleek::cache::Cache;
+use sleek::config::{Config, ConfigFile, ConfigResolver, EndOfLine};
+use sleek::diff::{self, ColorChoice};
+use sleek::encoding::{self, Decoded, Encoding};
+use sleek::files::{self, FileFinder};
+use sleek::markdown::format_markdown;
+use sleek::report::{self, FileReport, LineRange, ReportFormat, Status};
+use sleek::watch::Watcher;
+use sleek::{Dialect, Error, Language, format_range, format_ranges, format_str, lsp, verify};
+use sleek::{git, hook};
 
 fn main() {
     let options = Options::parse();
@@ -17,92 +29,732 @@ fn main() {
 }
 
 fn run(options: Options) -> Result<(), Error> {
-    let format_options = FormatOptions {
-        indent: Indent::Spaces(options.indent_spaces),
-        uppercase: Some(options.uppercase.unwrap_or(true)),
-        lines_between_queries: options.lines_between_queries,
-        ignore_case_convert: None,
+    let overrides = options.overrides();
+    let mut configs = match (&options.config, options.no_config) {
+        (Some(path), _) => ConfigResolver::fixed(ConfigFile::load(path)?, overrides),
+        (None, true) => ConfigResolver::disabled(overrides),
+        (None, false) => ConfigResolver::discover(overrides),
     };
 
+    match &options.command {
+        Some(Command::Lsp) => return lsp::run(configs),
+        Some(Command::Cache {
+            command: CacheCommand::Clear,
+        }) => {
+            let dir = Cache::dir();
+            match Cache::clear(&dir)? {
+                true => eprintln!("Cleared cache in {}", dir.display()),
+                false => eprintln!("No cache in {}", dir.display()),
+            }
+            return Ok(());
+        }
+        Some(Command::InstallHook { fix, force }) => {
+            let path = hook::install(*fix, *force)?;
+            eprintln!("Installed pre-commit hook in {}", path.display());
+            return Ok(());
+        }
+        None => {}
+    }
+
+    let mode = options.mode();
+    let formatter = options.formatter();
+
     match options.file_paths.is_empty() {
-        true => process_stdin(&format_options, options.check, &options),
-        false => process_files(&options.file_paths, &format_options, &options),
+        true => process_stdin(
+            &mut configs,
+            mode,
+            options.language.unwrap_or_default(),
+            options.range.or(options.byte_range),
+            &formatter,
+        ),
+        false if options.watch => {
+            let finder = FileFinder::new(&options.extensions, &options.exclude)?;
+            watch_files(
+                &options.file_paths,
+                &finder,
+                &configs,
+                mode,
+                options.language,
+                &formatter,
+            )
+        }
+        false => {
+            let finder = FileFinder::new(&options.extensions, &options.exclude)?;
+            let mut paths = finder.find(&options.file_paths)?;
+            if let Some(changed) = options.changed_files()? {
+                paths.retain(|path| {
+                    fs::canonicalize(path).is_ok_and(|path| changed.contains(&path))
+                });
+            }
+            // Files only partly formatted must not be recorded as formatted
+            let use_cache = options.lines_changed_since.is_none();
+            let cache = match options.cache && !options.no_cache && use_cache {
+                true => Some(Cache::load(&Cache::dir())?),
+                false => None,
+            };
+            let result = process_files(&paths, &mut configs, &options, cache.as_ref());
+            // Files found formatted before an error are still worth remembering
+            if let Some(cache) = &cache {
+                cache.save()?;
+            }
+            result
+        }
     }
 }
 
+/// What to do with each formatted input.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Mode {
+    /// Write the formatted output back (or to stdout).
+    Write,
+    /// Report every input that is not formatted, then fail.
+    Check,
+    /// Print only the paths of inputs that are not formatted, then fail.
+    ListDifferent,
+    /// Print a unified diff for every input that is not formatted, then fail.
+    Diff { color: bool },
+}
+
+/// How each input is read, formatted and checked.
+#[derive(Debug, Clone, Copy)]
+struct Formatter {
+    encoding: Encoding,
+    /// Refuse formatting that changes more than whitespace and keyword case.
+    verify: bool,
+    /// Format again until the output stops changing.
+    ensure_idempotent: bool,
+    /// Read files as staged in git rather than from the working tree.
+    staged_contents: bool,
+}
+
+/// Passes `--ensure-idempotent` makes before reporting an input as unstable.
+const MAX_PASSES: usize = 5;
+
+impl Formatter {
+    /// Reads the contents of `path` to format.
+    fn read(&self, path: &Path) -> Result<Vec<u8>, Error> {
+        match self.staged_contents {
+            true => git::staged_contents(path),
+            false => fs::read(path).map_err(|source| Error::Read {
+                path: path.to_path_buf(),
+                source,
+            }),
+        }
+    }
+
+    /// Decodes the contents of `path`, naming it in the error if they are not
+    /// valid in the chosen encoding.
+    fn decode(&self, bytes: &[u8], path: &Path) -> Result<Decoded, Error> {
+        encoding::decode(bytes, self.encoding).ok_or_else(|| Error::Decode {
+            path: path.to_path_buf(),
+            // Without a byte order mark, auto detection reads UTF-8
+            encoding: match self.encoding {
+                Encoding::Auto => Encoding::Utf8,
+                encoding => encoding,
+            },
+        })
+    }
+
+    /// Formats `input` as `language`, or only its statements on `lines` if
+    /// given, also returning the line numbers of any Markdown code blocks that
+    /// changed.
+    fn format(
+        &self,
+        input: &str,
+        language: Language,
+        config: &Config,
+        lines: Option<&[RangeInclusive<usize>]>,
+        path: &Path,
+    ) -> Result<(String, Vec<usize>), Error> {
+        // Later passes format the same statements where they ended up
+        let mut selected = lines.map(|lines| {
+            lines
+                .iter()
+                .map(|lines| InputRange::Lines(*lines.start(), *lines.end()).to_bytes(input))
+                .collect::<Vec<_>>()
+        });
+        let mut format_pass = |input: &str| -> Result<(String, Vec<usize>), Error> {
+            match (&mut selected, language) {
+                (None, _) => format_input(input, language, config),
+                (Some(ranges), Language::Sql) => {
+                    let (formatted, formatted_ranges) = format_statements(input, ranges, config)?;
+                    *ranges = formatted_ranges;
+                    Ok((formatted, Vec::new()))
+                }
+                (Some(_), Language::Markdown) => Err(Error::RangeUnsupported),
+            }
+        };
+        let (mut formatted, changed_blocks) = format_pass(input)?;
+
+        if self.ensure_idempotent {
+            let mut passes = 1;
+            loop {
+                let (again, _) = format_pass(&formatted)?;
+                if again == formatted {
+                    break;
+                }
+                passes += 1;
+                if passes == MAX_PASSES {
+                    let name = path.display().to_string();
+                    return Err(Error::Unstable {
+                        path: path.to_path_buf(),
+                        passes,
+                        diff: diff::unified_diff(&name, &formatted, &again, false),
+                    });
+                }
+                formatted = again;
+            }
+        }
+
+        self.check(input, &formatted, language, config, path)?;
+        Ok((formatted, changed_blocks))
+    }
+
+    /// Refuses formatting of `path` that changed more than whitespace and
+    /// keyword case, unless verification is disabled.
+    fn check(
+        &self,
+        input: &str,
+        formatted: &str,
+        language: Language,
+        config: &Config,
+        path: &Path,
+    ) -> Result<(), Error> {
+        if !self.verify {
+            return Ok(());
+        }
+        verify::verify(input, formatted, language, config.dialect).map_err(|mismatch| {
+            Error::Verify {
+                path: path.to_path_buf(),
+                mismatch,
+            }
+        })
+    }
+}
+
+/// Part of the input selected with `--range` or `--byte-range`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum InputRange {
+    /// 1-based, inclusive line numbers.
+    Lines(usize, usize),
+    /// 0-based byte offsets, with an exclusive end.
+    Bytes(usize, usize),
+}
+
+impl InputRange {
+    fn to_bytes(self, input: &str) -> Range<usize> {
+        match self {
+            InputRange::Lines(start, end) => {
+                let start = line_offset(input, start - 1);
+                let end = line_offset(input, end);
+                // Exclude the newline that ends the last selected line
+                let end = match input[..end].ends_with('\n') {
+                    true => end - 1,
+                    false => end,
+                };
+                start..end.max(start)
+            }
+            InputRange::Bytes(start, end) => start.min(input.len())..end.min(input.len()),
+        }
+    }
+}
+
+fn parse_range(value: &str) -> Result<(usize, usize), String> {
+    let (start, end) = value
+        .split_once(':')
+        .ok_or_else(|| format!("expected START:END, got '{value}'"))?;
+    let start = start.parse().map_err(|e| format!("invalid start: {e}"))?;
+    let end = end.parse().map_err(|e| format!("invalid end: {e}"))?;
+
+    match start <= end {
+        true => Ok((start, end)),
+        false => Err(format!("start {start} is after end {end}")),
+    }
+}
+
+fn parse_line_range(value: &str) -> Result<InputRange, String> {
+    match parse_range(value)? {
+        (0, _) => Err("line numbers start at 1".to_string()),
+        (start, end) => Ok(InputRange::Lines(start, end)),
+    }
+}
+
+fn parse_byte_range(value: &str) -> Result<InputRange, String> {
+    parse_range(value).map(|(start, end)| InputRange::Bytes(start, end))
+}
+
+/// Returns the byte offset where the 0-based line `line` starts, or the input
+/// length if there are fewer lines.
+fn line_offset(input: &str, line: usize) -> usize {
+    match line {
+        0 => 0,
+        line => input
+            .match_indices('\n')
+            .nth(line - 1)
+            .map_or(input.len(), |(i, _)| i + 1),
+    }
+}
+
+/// Returns the 1-based line number containing the byte offset `offset`.
+fn line_number(input: &str, offset: usize) -> usize {
+    input[..offset].matches('\n').count() + 1
+}
+
 fn process_stdin(
-    format_options: &FormatOptions,
-    check_only: bool,
-    options: &Options,
+    configs: &mut ConfigResolver,
+    mode: Mode,
+    language: Language,
+    range: Option<InputRange>,
+    formatter: &Formatter,
 ) -> Result<(), Error> {
-    let mut input = String::new();
-    io::stdin().read_to_string(&mut input)?;
+    let path = Path::new("<stdin>");
+    let mut bytes = Vec::new();
+    io::stdin().read_to_end(&mut bytes)?;
+    let decoded = formatter.decode(&bytes, path)?;
+    let input = decoded.text.clone();
 
-    let mut formatted = format(&input, &QueryParams::default(), format_options);
+    let config = configs.config_for(&std::env::current_dir()?)?;
 
-    if options.trailing_newline.unwrap_or(true) && !formatted.ends_with('\n') {
-        formatted.push('\n');
-    }
+    let formatted = match range {
+        Some(_) if language != Language::Sql => return Err(Error::RangeUnsupported),
+        Some(range) => match format_range(&input, range.to_bytes(&input), &config)? {
+            Some(edit) => {
+                let mut formatted = input.clone();
+                formatted.replace_range(edit.range.clone(), &edit.text);
 
-    if check_only {
+                if mode == Mode::Write {
+                    let new_end = edit.range.start + edit.text.len();
+                    eprintln!(
+                        "Replaced bytes {}..{} (lines {}:{}) with bytes {}..{} (lines {}:{})",
+                        edit.range.start,
+                        edit.range.end,
+                        line_number(&input, edit.range.start),
+                        line_number(&input, edit.range.end),
+                        edit.range.start,
+                        new_end,
+                        line_number(&formatted, edit.range.start),
+                        line_number(&formatted, new_end),
+                    );
+                }
+                formatter.check(&input, &formatted, language, &config, path)?;
+                formatted
+            }
+            None => input.clone(),
+        },
+        None => formatter.format(&input, language, &config, None, path)?.0,
+    };
+
+    if mode != Mode::Write {
         if input != formatted {
+            if let Mode::Diff { color } = mode {
+                let diff = diff::unified_diff("<stdin>", &input, &formatted, color);
+                io::stdout().write_all(diff.as_bytes())?;
+            }
             return Err(Error::Check);
         }
         return Ok(());
     }
 
-    io::stdout().write_all(formatted.as_bytes())?;
+    let bytes = encode(&decoded, &formatted, path)?;
+    io::stdout().write_all(&bytes)?;
     Ok(())
 }
 
 fn process_files(
-    file_paths: &[String],
-    format_options: &FormatOptions,
+    paths: &[PathBuf],
+    configs: &mut ConfigResolver,
     options: &Options,
+    cache: Option<&Cache>,
 ) -> Result<(), Error> {
-    for file_path in file_paths {
-        let entries = glob(file_path)?;
-        for entry in entries {
-            let path = entry?;
-            let input = fs::read_to_string(&path)?;
+    let mode = options.mode();
+    let formatter = options.formatter();
+    let configs = paths
+        .iter()
+        .map(|path| configs.config_for(path))
+        .collect::<Result<Vec<_>, _>>()?;
+    let changed_lines = match &options.lines_changed_since {
+        Some(rev) => Some(git::changed_lines(rev)?),
+        None => None,
+    };
+    let lines: Vec<_> = paths
+        .iter()
+        .map(|path| changed_lines.as_ref().and_then(|changed| changed.get(path)))
+        .collect();
+    let files: Vec<_> = paths.iter().zip(&configs).zip(&lines).collect();
 
-            let mut formatted = format(&input, &QueryParams::default(), format_options);
+    let outcomes = parallel_map(&files, options.jobs(), |&((path, config), lines)| {
+        let language = options
+            .language
+            .unwrap_or_else(|| Language::from_path(path));
+        process_file(path, config, mode, language, &formatter, *lines, cache)
+    });
 
-            if options.trailing_newline.unwrap_or(true) && !formatted.ends_with('\n') {
-                formatted.push('\n');
+    if let Some(format) = options.report() {
+        let records = file_reports(paths, &outcomes);
+        let report = report::render(format, &records)?;
+        match &options.report_file {
+            Some(report_file) => fs::write(report_file, report).map_err(|source| Error::Write {
+                path: report_file.clone(),
+                source,
+            })?,
+            // Workflow commands are read from the log, next to the usual output
+            None if format == ReportFormat::Github => io::stdout().write_all(report.as_bytes())?,
+            // The report takes the place of the usual output
+            None => {
+                io::stdout().write_all(report.as_bytes())?;
+                return report_status(&records);
             }
+        }
+    }
 
-            if options.check {
-                if input != formatted {
-                    return Err(Error::Check);
-                }
-                // Continue checking other files - don't return early
-            } else {
-                fs::write(&path, formatted)?;
+    // Report in path order once every file is done, so output is deterministic.
+    // A file that could not be processed does not hide the files after it.
+    let mut unformatted = 0;
+    let mut failed = 0;
+    for (path, outcome) in paths.iter().zip(outcomes) {
+        match outcome {
+            Ok(outcome) if outcome.change == Change::Unformatted => {
+                report_unformatted(path, &outcome, mode)?;
+                unformatted += 1;
+            }
+            Ok(_) => {}
+            Err(e) => {
+                eprintln!("{e}");
+                failed += 1;
             }
         }
     }
+
+    match (failed, unformatted) {
+        (0, 0) => Ok(()),
+        (0, count) => Err(Error::CheckFiles { count }),
+        (count, 0) => Err(Error::FailedFiles { count }),
+        (count, unformatted) => {
+            eprintln!("{}", Error::CheckFiles { count: unformatted });
+            Err(Error::FailedFiles { count })
+        }
+    }
+}
+
+/// Builds the report record of every file, including those that could not be
+/// processed.
+fn file_reports(paths: &[PathBuf], outcomes: &[Result<Outcome, Error>]) -> Vec<FileReport> {
+    paths
+        .iter()
+        .zip(outcomes)
+        .map(|(path, outcome)| {
+            let path = path.display().to_string();
+            match outcome {
+                Ok(outcome) => FileReport {
+                    path,
+                    status: match outcome.change {
+                        Change::Unchanged => Status::Unchanged,
+                        Change::Written => Status::Formatted,
+                        Change::Unformatted => Status::WouldReformat,
+                    },
+                    error: None,
+                    bytes_before: Some(outcome.bytes_before),
+                    bytes_after: Some(outcome.bytes_after),
+                    changed_lines: outcome
+                        .changed_lines
+                        .iter()
+                        .map(|lines| LineRange {
+                            start: *lines.start(),
+                            end: *lines.end(),
+                        })
+                        .collect(),
+                    diff: Some(outcome.diff.clone()).filter(|diff| !diff.is_empty()),
+                },
+                Err(error) => FileReport {
+                    path,
+                    status: Status::Error,
+                    error: Some(error.into()),
+                    bytes_before: None,
+                    bytes_after: None,
+                    changed_lines: Vec::new(),
+                    diff: None,
+                },
+            }
+        })
+        .collect()
+}
+
+/// Fails if any file in `records` could not be processed or is not formatted.
+fn report_status(records: &[FileReport]) -> Result<(), Error> {
+    let count = |status| records.iter().filter(|r| r.status == status).count();
+    match (count(Status::Error), count(Status::WouldReformat)) {
+        (0, 0) => Ok(()),
+        (0, count) => Err(Error::CheckFiles { count }),
+        (count, _) => Err(Error::FailedFiles { count }),
+    }
+}
+
+/// Reports a file that is not formatted the way `mode` asks for.
+fn report_unformatted(path: &Path, outcome: &Outcome, mode: Mode) -> Result<(), Error> {
+    match mode {
+        Mode::Write => {}
+        Mode::Check => match outcome.changed_blocks.as_slice() {
+            [] => eprintln!("Would reformat: {}", path.display()),
+            blocks => {
+                for line in blocks {
+                    eprintln!("Would reformat: {}:{line} (SQL block)", path.display());
+                }
+            }
+        },
+        Mode::ListDifferent => println!("{}", path.display()),
+        Mode::Diff { .. } => io::stdout().write_all(outcome.diff.as_bytes())?,
+    }
     Ok(())
 }
 
-#[derive(Error, Debug)]
-enum Error {
-    #[error("Failed to read from stdin: {0}")]
-    Io(#[from] io::Error),
-    #[error("Failed to read glob pattern: {0}")]
-    Glob(#[from] glob::GlobError),
-    #[error("Failed to read glob pattern: {0}")]
-    Pattern(#[from] glob::PatternError),
-    #[error("Input is not formatted correctly. Run without --check to format the input.")]
-    Check,
-    #[error("Failed to append a trailing newline to the formatted SQL.")]
-    Format(#[from] std::fmt::Error),
+/// Formats files matched by `file_paths` whenever they change, until stopped.
+///
+/// Errors in a single file are logged and watching carries on. Config files are
+/// read again for every batch of changes.
+fn watch_files(
+    file_paths: &[String],
+    finder: &FileFinder,
+    configs: &ConfigResolver,
+    mode: Mode,
+    language: Option<Language>,
+    formatter: &Formatter,
+) -> Result<(), Error> {
+    let watcher = Watcher::new(&finder.roots(file_paths))?;
+    eprintln!("Watching for changes, press Ctrl-C to stop");
+
+    // What sleek last wrote to each file, so its own writes are not formatted again
+    let mut written: HashMap<PathBuf, Vec<u8>> = HashMap::new();
+
+    loop {
+        let changes = watcher.changes()?;
+        let targets: HashMap<PathBuf, PathBuf> = match finder.find(file_paths) {
+            Ok(paths) => paths
+                .into_iter()
+                .filter_map(|path| Some((fs::canonicalize(&path).ok()?, path)))
+                .collect(),
+            Err(e) => {
+                eprintln!("{e}");
+                continue;
+            }
+        };
+        let mut configs = configs.clone();
+
+        let mut seen = HashSet::new();
+        for changed in changes {
+            // Removed files and temporary files from writes are not targets
+            let Ok(canonical) = fs::canonicalize(&changed) else {
+                continue;
+            };
+            let Some(path) = targets.get(&canonical) else {
+                continue;
+            };
+            if !seen.insert(canonical.clone()) {
+                continue;
+            }
+            let contents = fs::read(path).ok();
+            if contents.is_some() && written.get(&canonical) == contents.as_ref() {
+                continue;
+            }
+
+            let language = language.unwrap_or_else(|| Language::from_path(path));
+            let outcome = configs.config_for(path).and_then(|config| {
+                process_file(path, &config, mode, language, formatter, None, None)
+            });
+            match outcome {
+                Ok(outcome) => match outcome.change {
+                    Change::Written => {
+                        eprintln!("Formatted {}", path.display());
+                        if let Ok(contents) = fs::read(path) {
+                            written.insert(canonical, contents);
+                        }
+                    }
+                    Change::Unchanged => {}
+                    Change::Unformatted => report_unformatted(path, &outcome, mode)?,
+                },
+                Err(e) => eprintln!("{e}"),
+            }
+        }
+    }
+}
+
+/// What happened to a file, reported after every file has been processed.
+struct Outcome {
+    change: Change,
+    /// Size of the file as read.
+    bytes_before: usize,
+    /// Size of the formatted file, whether or not it was written.
+    bytes_after: usize,
+    /// 1-based lines of the Markdown code blocks that changed.
+    changed_blocks: Vec<usize>,
+    /// 1-based, inclusive ranges of the lines that formatting changes.
+    changed_lines: Vec<RangeInclusive<usize>>,
+    /// A unified diff of the changes, only made in diff mode.
+    diff: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Change {
+    /// The file was already formatted.
+    Unchanged,
+    /// The file was formatted and written back.
+    Written,
+    /// The file is not formatted and was left as it is.
+    Unformatted,
+}
+
+/// Reads, formats and, in write mode, writes back a single file.
+fn process_file(
+    path: &Path,
+    config: &Config,
+    mode: Mode,
+    language: Language,
+    formatter: &Formatter,
+    lines: Option<&[RangeInclusive<usize>]>,
+    cache: Option<&Cache>,
+) -> Result<Outcome, Error> {
+    let bytes = formatter.read(path)?;
+    let mut outcome = Outcome {
+        change: Change::Unchanged,
+        bytes_before: bytes.len(),
+        bytes_after: bytes.len(),
+        changed_blocks: Vec::new(),
+        changed_lines: Vec::new(),
+        diff: String::new(),
+    };
+    let settings = (config, language, formatter);
+    if let Some(cache) = cache {
+        if cache.contains(Cache::key(&bytes, &settings)) {
+            return Ok(outcome);
+        }
+    }
+
+    let decoded = formatter.decode(&bytes, path)?;
+    let input = &decoded.text;
+    let (formatted, changed_blocks) = formatter.format(input, language, config, lines, path)?;
+
+    // Unchanged files are not rewritten, so their mtimes stay untouched
+    if *input == formatted {
+        if let Some(cache) = cache {
+            cache.insert(Cache::key(&bytes, &settings));
+        }
+        return Ok(outcome);
+    }
+
+    let encoded = encode(&decoded, &formatted, path)?;
+    outcome.bytes_after = encoded.len();
+    outcome.changed_blocks = changed_blocks;
+    outcome.changed_lines = diff::changed_lines(input, &formatted);
+    match mode {
+        Mode::Write => {
+            files::write_atomic(path, &encoded)?;
+            // Formatting once is not always a fixed point, so the output is
+            // only known to be formatted once --ensure-idempotent has settled it
+            if let Some(cache) = cache.filter(|_| formatter.ensure_idempotent) {
+                cache.insert(Cache::key(&encoded, &settings));
+            }
+            outcome.change = Change::Written;
+        }
+        Mode::Diff { color } => {
+            let name = path.display().to_string();
+            outcome.diff = diff::unified_diff(&name, input, &formatted, color);
+            outcome.change = Change::Unformatted;
+        }
+        Mode::Check | Mode::ListDifferent => outcome.change = Change::Unformatted,
+    }
+    Ok(outcome)
+}
+
+/// Calls `f` on each item using up to `jobs` threads, returning the results in
+/// the order of `items`.
+fn parallel_map<T, R, F>(items: &[T], jobs: usize, f: F) -> Vec<R>
+where
+    T: Sync,
+    R: Send,
+    F: Fn(&T) -> R + Sync,
+{
+    let jobs = jobs.min(items.len());
+    if jobs <= 1 {
+        return items.iter().map(f).collect();
+    }
+
+    let next = AtomicUsize::new(0);
+    let mut results: Vec<(usize, R)> = thread::scope(|scope| {
+        let workers: Vec<_> = (0..jobs)
+            .map(|_| {
+                scope.spawn(|| {
+                    let mut results = Vec::new();
+                    loop {
+                        let i = next.fetch_add(1, Ordering::Relaxed);
+                        let Some(item) = items.get(i) else {
+                            return results;
+                        };
+                        results.push((i, f(item)));
+                    }
+                })
+            })
+            .collect();
+
+        workers
+            .into_iter()
+            .flat_map(|worker| worker.join().unwrap_or_else(|e| panic::resume_unwind(e)))
+            .collect()
+    });
+
+    results.sort_by_key(|&(i, _)| i);
+    results.into_iter().map(|(_, result)| result).collect()
+}
+
+/// Encodes `formatted` the way `decoded` was read from `path`.
+fn encode(decoded: &Decoded, formatted: &str, path: &Path) -> Result<Vec<u8>, Error> {
+    decoded.encode(formatted).ok_or_else(|| Error::Encode {
+        path: path.to_path_buf(),
+        encoding: decoded.encoding,
+    })
+}
+
+/// Formats `input` as `language`, also returning the line numbers of any
+/// Markdown code blocks that changed.
+fn format_input(
+    input: &str,
+    language: Language,
+    config: &Config,
+) -> Result<(String, Vec<usize>), Error> {
+    match language {
+        Language::Sql => Ok((format_str(input, config)?, Vec::new())),
+        Language::Markdown => {
+            let formatted = format_markdown(input, config)?;
+            Ok((formatted.text, formatted.changed_blocks))
+        }
+    }
+}
+
+/// Formats only the statements of `input` overlapping `ranges`, returning the
+/// result and the byte ranges of the formatted statements in it.
+fn format_statements(
+    input: &str,
+    ranges: &[Range<usize>],
+    config: &Config,
+) -> Result<(String, Vec<Range<usize>>), Error> {
+    let mut formatted = String::with_capacity(input.len());
+    let mut formatted_ranges = Vec::new();
+    let mut pos = 0;
+    for edit in format_ranges(input, ranges, config)? {
+        formatted.push_str(&input[pos..edit.range.start]);
+        formatted_ranges.push(formatted.len()..formatted.len() + edit.text.len());
+        formatted.push_str(&edit.text);
+        pos = edit.range.end;
+    }
+    formatted.push_str(&input[pos..]);
+    Ok((formatted, formatted_ranges))
 }
 
 #[derive(Parser)]
-#[command(author, version, about, long_about = None)]
+#[command(author, version, about, long_about = None, disable_help_subcommand = true)]
 struct Options {
-    /// File path(s) to format, supports glob patterns.
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    /// File path(s) to format, supports glob patterns and directories.
     /// If no file paths are provided, reads from stdin.
     #[arg(value_name = "FILE")]
     file_paths: Vec<String>,
@@ -111,19 +763,242 @@ struct Options {
     #[arg(short, long)]
     check: bool,
 
-    /// Number of spaces to use for indentation
-    #[arg(short, long, value_name = "NUM", default_value = "4")]
-    indent_spaces: u8,
+    /// Print the paths of files that are not formatted, without modifying them
+    #[arg(long, conflicts_with = "diff")]
+    list_different: bool,
+
+    /// Print a unified diff of the changes formatting would make, without modifying files
+    #[arg(long)]
+    diff: bool,
+
+    /// When to colorize diff output
+    #[arg(long, value_name = "WHEN", default_value = "auto")]
+    color: ColorChoice,
+
+    /// Number of spaces to use for indentation (default: 4)
+    #[arg(short, long, value_name = "NUM")]
+    indent_spaces: Option<u8>,
 
     /// Convert reserved keywords to UPPERCASE (default: true)
     #[arg(short = 'U', long, value_name = "BOOL")]
     uppercase: Option<bool>,
 
-    /// Number of line breaks to insert after each query
-    #[arg(short, long, value_name = "NUM", default_value = "2")]
-    lines_between_queries: u8,
+    /// Number of line breaks to insert after each query (default: 2)
+    #[arg(short, long, value_name = "NUM")]
+    lines_between_queries: Option<u8>,
 
     /// Ensure files end with a trailing newline (default: true)
     #[arg(short = 'n', long, value_name = "BOOL")]
     trailing_newline: Option<bool>,
+
+    /// SQL dialect to read input as (default: accept a mix of common dialects)
+    #[arg(short, long, value_name = "DIALECT")]
+    dialect: Option<Dialect>,
+
+    /// Line endings of formatted output (default: auto)
+    #[arg(long, value_name = "EOL")]
+    end_of_line: Option<EndOfLine>,
+
+    /// Character encoding to read and write input with
+    #[arg(long, value_name = "ENCODING", default_value = "auto")]
+    encoding: Encoding,
+
+    /// Format each input again until the output stops changing, reporting inputs that never settle
+    #[arg(long)]
+    ensure_idempotent: bool,
+
+    /// Skip checking that formatting changed nothing but whitespace and keyword case
+    #[arg(long)]
+    no_verify: bool,
+
+    /// Format input as this language instead of detecting it from the file extension
+    #[arg(long, value_name = "LANGUAGE")]
+    language: Option<Language>,
+
+    /// Only format the statements overlapping these lines of stdin, as START:END (1-based, inclusive)
+    #[arg(
+        long,
+        value_name = "START:END",
+        value_parser = parse_line_range,
+        conflicts_with_all = ["file_paths", "byte_range"]
+    )]
+    range: Option<InputRange>,
+
+    /// Only format the statements overlapping these bytes of stdin, as START:END (end exclusive)
+    #[arg(
+        long,
+        value_name = "START:END",
+        value_parser = parse_byte_range,
+        conflicts_with = "file_paths"
+    )]
+    byte_range: Option<InputRange>,
+
+    /// Number of files to format at once (default: number of CPUs)
+    #[arg(short, long, value_name = "N")]
+    jobs: Option<NonZeroUsize>,
+
+    /// Print a machine-readable report of every file to stdout instead of the usual output (default: github in GitHub Actions, except with --list-different or --diff)
+    #[arg(
+        long,
+        value_name = "FORMAT",
+        requires = "file_paths",
+        conflicts_with = "watch"
+    )]
+    report: Option<ReportFormat>,
+
+    /// Write the report to this file, keeping the usual output
+    #[arg(long, value_name = "PATH", requires = "report")]
+    report_file: Option<PathBuf>,
+
+    /// Only format matched files that differ from this git revision, including uncommitted and untracked files
+    #[arg(
+        long,
+        value_name = "REV",
+        requires = "file_paths",
+        conflicts_with = "watch"
+    )]
+    changed_since: Option<String>,
+
+    /// Only format matched files with changes staged in git, checking their staged contents with --check, --diff or --list-different
+    #[arg(
+        long,
+        requires = "file_paths",
+        conflicts_with_all = ["watch", "changed_since"]
+    )]
+    staged: bool,
+
+    /// Only format the statements on lines that differ from this git revision, leaving the rest of each file untouched
+    #[arg(
+        long,
+        value_name = "REV",
+        requires = "file_paths",
+        conflicts_with_all = ["watch", "changed_since", "staged"]
+    )]
+    lines_changed_since: Option<String>,
+
+    /// Keep running and format matched files whenever they change
+    #[arg(short, long, requires = "file_paths")]
+    watch: bool,
+
+    /// Skip files that the cache records as formatted with the same settings
+    #[arg(long)]
+    cache: bool,
+
+    /// Do not read or update the cache, even if --cache is given
+    #[arg(long)]
+    no_cache: bool,
+
+    /// File extension to format when walking directories, may be repeated (default: sql)
+    #[arg(long = "extension", value_name = "EXT")]
+    extensions: Vec<String>,
+
+    /// Skip files and directories matching this glob pattern, may be repeated
+    #[arg(long, value_name = "GLOB")]
+    exclude: Vec<String>,
+
+    /// Use this config file instead of searching for sleek.toml or pyproject.toml
+    #[arg(long, value_name = "PATH", conflicts_with = "no_config", global = true)]
+    config: Option<PathBuf>,
+
+    /// Ignore any sleek.toml or pyproject.toml config files
+    #[arg(long, global = true)]
+    no_config: bool,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Run a language server over stdio for editor integration
+    Lsp,
+    /// Manage the cache of formatted files
+    Cache {
+        #[command(subcommand)]
+        command: CacheCommand,
+    },
+    /// Install a git pre-commit hook that checks the staged SQL files
+    InstallHook {
+        /// Format the staged SQL files and stage the result instead of checking them
+        #[arg(long)]
+        fix: bool,
+        /// Replace an existing pre-commit hook
+        #[arg(long)]
+        force: bool,
+    },
+}
+
+#[derive(Subcommand)]
+enum CacheCommand {
+    /// Delete the cache
+    Clear,
+}
+
+impl Options {
+    fn mode(&self) -> Mode {
+        if self.list_different {
+            Mode::ListDifferent
+        } else if self.diff {
+            Mode::Diff {
+                color: self.color.enabled() && self.report().is_none(),
+            }
+        } else if self.check {
+            Mode::Check
+        } else {
+            Mode::Write
+        }
+    }
+
+    /// The report to produce: the one asked for, or GitHub annotations when
+    /// formatting or checking files in a GitHub Actions workflow. Annotations
+    /// are not added to the output of `--list-different` and `--diff`, which
+    /// is meant to be read by other tools.
+    fn report(&self) -> Option<ReportFormat> {
+        let in_github_actions = env::var_os("GITHUB_ACTIONS").is_some_and(|value| value == "true");
+        let machine_output = self.list_different || self.diff;
+        self.report.or_else(|| {
+            (in_github_actions && !self.file_paths.is_empty() && !self.watch && !machine_output)
+                .then_some(ReportFormat::Github)
+        })
+    }
+
+    /// Asks git for the files to limit formatting to, if `--changed-since`,
+    /// `--lines-changed-since` or `--staged` is given.
+    fn changed_files(&self) -> Result<Option<HashSet<PathBuf>>, Error> {
+        let since = self
+            .changed_since
+            .as_ref()
+            .or(self.lines_changed_since.as_ref());
+        match (since, self.staged) {
+            (Some(rev), _) => git::changed_since(rev).map(Some),
+            (None, true) => git::staged().map(Some),
+            (None, false) => Ok(None),
+        }
+    }
+
+    fn jobs(&self) -> usize {
+        self.jobs
+            .or_else(|| thread::available_parallelism().ok())
+            .map_or(1, NonZeroUsize::get)
+    }
+
+    fn formatter(&self) -> Formatter {
+        Formatter {
+            encoding: self.encoding,
+            verify: !self.no_verify,
+            ensure_idempotent: self.ensure_idempotent,
+            // Checks look at what would be committed, formatting at the files
+            staged_contents: self.staged && !matches!(self.mode(), Mode::Write),
+        }
+    }
+
+    /// Settings given explicitly on the command line, which take precedence over
+    /// any config file.
+    fn overrides(&self) -> ConfigFile {
+        ConfigFile {
+            indent_spaces: self.indent_spaces,
+            uppercase: self.uppercase,
+            lines_between_queries: self.lines_between_queries,
+            trailing_newline: self.trailing_newline,
+            dialect: self.dialect,
+            end_of_line: self.end_of_line,
+        }
+    }
 }
@@ -0,0 +1,181 @@
+use std::ops::Range;
+
+use crate::config::EndOfLine;
+use crate::statements::next_line;
+use crate::{Config, Error, format_str};
+
+/// Info strings of fenced code blocks that are formatted as SQL.
+pub const SQL_LANGUAGES: &[&str] = &["sql", "postgresql", "mysql"];
+
+/// The result of formatting a Markdown document.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FormattedMarkdown {
+    /// The document with its SQL code blocks formatted.
+    pub text: String,
+    /// 1-based line numbers of the opening fences of blocks that changed.
+    pub changed_blocks: Vec<usize>,
+}
+
+/// Formats each SQL fenced code block in a Markdown document.
+///
+/// Everything outside the SQL blocks, including the fences themselves, is
+/// kept as written. Blocks indented inside list items keep their indentation.
+/// The whole document gets the line endings chosen by `config.end_of_line`.
+pub fn format_markdown(input: &str, config: &Config) -> Result<FormattedMarkdown, Error> {
+    let end_of_line = config.end_of_line.resolve(input);
+    let input = &input.replace("\r\n", "\n");
+    let mut config = config.clone();
+    config.end_of_line = EndOfLine::Lf;
+    let config = &config;
+
+    let mut text = String::with_capacity(input.len());
+    let mut changed_blocks = Vec::new();
+    let mut pos = 0;
+
+    for block in code_blocks(input) {
+        let content = &input[block.content.clone()];
+        text.push_str(&input[pos..block.content.start]);
+        match block.fence.is_sql() {
+            true => {
+                let formatted = format_block(content, block.fence.indent, config)?;
+                if formatted != content {
+                    changed_blocks.push(input[..block.start].matches('\n').count() + 1);
+                }
+                text.push_str(&formatted);
+            }
+            false => text.push_str(content),
+        }
+        pos = block.content.end;
+    }
+    text.push_str(&input[pos..]);
+
+    if end_of_line != "\n" {
+        text = text.replace('\n', end_of_line);
+    }
+
+    Ok(FormattedMarkdown {
+        text,
+        changed_blocks,
+    })
+}
+
+/// Returns the byte ranges of the contents of each SQL fenced code block.
+pub fn sql_blocks(input: &str) -> Vec<Range<usize>> {
+    code_blocks(input)
+        .into_iter()
+        .filter(|block| block.fence.is_sql())
+        .map(|block| block.content)
+        .collect()
+}
+
+/// A closed fenced code block.
+struct CodeBlock<'a> {
+    fence: Fence<'a>,
+    /// Start of the opening fence line.
+    start: usize,
+    /// The lines between the fences.
+    content: Range<usize>,
+}
+
+/// Finds the closed fenced code blocks of a document. An unclosed block runs to
+/// the end of the document, so it and everything after it are left out.
+fn code_blocks(input: &str) -> Vec<CodeBlock<'_>> {
+    let mut blocks = Vec::new();
+    let mut pos = 0;
+
+    while pos < input.len() {
+        let line_end = next_line(input, pos);
+        let Some(fence) = Fence::parse(&input[pos..line_end]) else {
+            pos = line_end;
+            continue;
+        };
+
+        let mut close = line_end;
+        while close < input.len() && !fence.is_closed_by(&input[close..next_line(input, close)]) {
+            close = next_line(input, close);
+        }
+        if close == input.len() {
+            break;
+        }
+
+        blocks.push(CodeBlock {
+            fence,
+            start: pos,
+            content: line_end..close,
+        });
+        pos = next_line(input, close);
+    }
+
+    blocks
+}
+
+/// An opening code fence, such as ```` ```sql ```` or `~~~postgresql`.
+struct Fence<'a> {
+    indent: &'a str,
+    marker: char,
+    length: usize,
+    language: String,
+}
+
+impl<'a> Fence<'a> {
+    fn parse(line: &'a str) -> Option<Self> {
+        let rest = line.trim_start_matches([' ', '\t']);
+        let indent = &line[..line.len() - rest.len()];
+
+        let marker = rest.chars().next().filter(|c| matches!(c, '`' | '~'))?;
+        let length = rest.chars().take_while(|&c| c == marker).count();
+        let info = rest[length..].trim();
+        if length < 3 || (marker == '`' && info.contains('`')) {
+            return None;
+        }
+
+        let language = info
+            .split_whitespace()
+            .next()
+            .unwrap_or_default()
+            .to_lowercase();
+
+        Some(Fence {
+            indent,
+            marker,
+            length,
+            language,
+        })
+    }
+
+    fn is_closed_by(&self, line: &str) -> bool {
+        let line = line.trim();
+        line.chars().count() >= self.length && line.chars().all(|c| c == self.marker)
+    }
+
+    fn is_sql(&self) -> bool {
+        SQL_LANGUAGES.contains(&self.language.as_str())
+    }
+}
+
+/// Formats the content of a code block whose fence is indented by `indent`.
+fn format_block(content: &str, indent: &str, config: &Config) -> Result<String, Error> {
+    let dedented: String = content
+        .split_inclusive('\n')
+        .map(|line| {
+            let spaces = line.len() - line.trim_start_matches([' ', '\t']).len();
+            &line[spaces.min(indent.len())..]
+        })
+        .collect();
+    if dedented.trim().is_empty() {
+        return Ok(content.to_string());
+    }
+
+    // The closing fence must start on its own line
+    let mut config = config.clone();
+    config.trailing_newline = true;
+    let formatted = format_str(&dedented, &config)?;
+
+    Ok(formatted
+        .split_inclusive('\n')
+        .map(|line| match line.trim().is_empty() {
+            true => line.trim_start_matches([' ', '\t']).to_string(),
+            false => format!("{indent}{line}"),
+        })
+        .collect())
+}
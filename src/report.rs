@@ -0,0 +1,351 @@
+use std::fmt::Write;
+
+use clap::ValueEnum;
+use serde::Serialize;
+use serde_json::json;
+
+use crate::Error;
+
+/// Version of the JSON report schema. It only changes when a field is removed
+/// or changes meaning; new fields may be added within a version.
+pub const JSON_REPORT_VERSION: u32 = 1;
+
+/// Where the SARIF report points for more about the tool.
+const INFORMATION_URI: &str = "https://github.com/nrempel/sleek";
+
+/// A machine-readable report of the files processed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ReportFormat {
+    /// A JSON document with a record for each file
+    Json,
+    /// SARIF 2.1.0, for code scanning
+    Sarif,
+    /// Checkstyle XML
+    Checkstyle,
+    /// JUnit XML, with a test case for each file
+    Junit,
+    /// GitHub Actions workflow commands, printed along with the usual output
+    Github,
+}
+
+/// What happened to a file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Status {
+    /// The file was already formatted.
+    Unchanged,
+    /// The file was formatted and written back.
+    Formatted,
+    /// The file is not formatted and was left as it is.
+    WouldReformat,
+    /// The file could not be processed.
+    Error,
+}
+
+/// The record of a single file in a report.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct FileReport {
+    pub path: String,
+    pub status: Status,
+    /// Why the file could not be processed, for [`Status::Error`].
+    pub error: Option<ErrorReport>,
+    /// Size of the file as read, if it could be read.
+    pub bytes_before: Option<usize>,
+    /// Size of the formatted file, whether or not it was written.
+    pub bytes_after: Option<usize>,
+    /// Lines of the file as read that formatting changes.
+    pub changed_lines: Vec<LineRange>,
+    /// A unified diff of the changes, when asked for with `--diff`.
+    pub diff: Option<String>,
+}
+
+/// A 1-based, inclusive range of lines.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct LineRange {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl LineRange {
+    fn describe(self) -> String {
+        match self.start == self.end {
+            true => format!("Line {} is not formatted", self.start),
+            false => format!("Lines {}-{} are not formatted", self.start, self.end),
+        }
+    }
+}
+
+/// An error in a [`FileReport`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ErrorReport {
+    /// The kind of error, from [`Error::kind`].
+    pub kind: &'static str,
+    pub message: String,
+}
+
+impl From<&Error> for ErrorReport {
+    fn from(error: &Error) -> Self {
+        ErrorReport {
+            kind: error.kind(),
+            message: error.to_string(),
+        }
+    }
+}
+
+/// A problem to report: an unformatted region or a file that failed.
+struct Finding<'a> {
+    file: &'a FileReport,
+    /// The lines it applies to, or `None` for the whole file.
+    lines: Option<LineRange>,
+    message: String,
+    is_error: bool,
+}
+
+/// Lists the findings for `file`: one for each changed region if it is not
+/// formatted, or the whole file if the regions are unknown, and one if it could
+/// not be processed.
+fn findings(file: &FileReport) -> Vec<Finding<'_>> {
+    match file.status {
+        Status::Error => vec![Finding {
+            file,
+            lines: None,
+            message: file
+                .error
+                .as_ref()
+                .map_or_else(String::new, |e| e.message.clone()),
+            is_error: true,
+        }],
+        Status::WouldReformat if file.changed_lines.is_empty() => vec![Finding {
+            file,
+            lines: None,
+            message: "File is not formatted".to_string(),
+            is_error: false,
+        }],
+        Status::WouldReformat => file
+            .changed_lines
+            .iter()
+            .map(|&lines| Finding {
+                file,
+                lines: Some(lines),
+                message: lines.describe(),
+                is_error: false,
+            })
+            .collect(),
+        Status::Unchanged | Status::Formatted => Vec::new(),
+    }
+}
+
+/// Renders `files` as a report in `format`, ending with a newline.
+pub fn render(format: ReportFormat, files: &[FileReport]) -> Result<String, Error> {
+    match format {
+        ReportFormat::Json => to_json(files),
+        ReportFormat::Sarif => to_sarif(files),
+        ReportFormat::Checkstyle => Ok(to_checkstyle(files)),
+        ReportFormat::Junit => Ok(to_junit(files)),
+        ReportFormat::Github => Ok(to_github(files)),
+    }
+}
+
+#[derive(Serialize)]
+struct JsonReport<'a> {
+    version: u32,
+    files: &'a [FileReport],
+}
+
+/// Renders `files` as a JSON report, ending with a newline.
+pub fn to_json(files: &[FileReport]) -> Result<String, Error> {
+    let report = JsonReport {
+        version: JSON_REPORT_VERSION,
+        files,
+    };
+    let mut json = serde_json::to_string_pretty(&report)?;
+    json.push('\n');
+    Ok(json)
+}
+
+/// Renders `files` as a SARIF 2.1.0 log, with a result for each finding.
+pub fn to_sarif(files: &[FileReport]) -> Result<String, Error> {
+    let results: Vec<_> = files
+        .iter()
+        .flat_map(findings)
+        .map(|finding| {
+            let mut location = json!({
+                "artifactLocation": { "uri": uri(&finding.file.path) },
+            });
+            if let Some(lines) = finding.lines {
+                location["region"] = json!({ "startLine": lines.start, "endLine": lines.end });
+            }
+            json!({
+                "ruleId": if finding.is_error { "error" } else { "unformatted" },
+                "level": if finding.is_error { "error" } else { "warning" },
+                "message": { "text": finding.message },
+                "locations": [{ "physicalLocation": location }],
+            })
+        })
+        .collect();
+
+    let log = json!({
+        "$schema": "https://json.schemastore.org/sarif-2.1.0.json",
+        "version": "2.1.0",
+        "runs": [{
+            "tool": {
+                "driver": {
+                    "name": "sleek",
+                    "version": env!("CARGO_PKG_VERSION"),
+                    "informationUri": INFORMATION_URI,
+                    "rules": [
+                        {
+                            "id": "unformatted",
+                            "shortDescription": { "text": "SQL is not formatted" },
+                        },
+                        {
+                            "id": "error",
+                            "shortDescription": { "text": "File could not be formatted" },
+                        },
+                    ],
+                },
+            },
+            "results": results,
+        }],
+    });
+
+    let mut sarif = serde_json::to_string_pretty(&log)?;
+    sarif.push('\n');
+    Ok(sarif)
+}
+
+/// Renders `files` as Checkstyle XML, listing every file with an error element
+/// for each finding.
+pub fn to_checkstyle(files: &[FileReport]) -> String {
+    let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    xml.push_str("<checkstyle version=\"4.3\">\n");
+
+    for file in files {
+        let _ = writeln!(xml, "  <file name=\"{}\">", escape(&file.path));
+        for finding in findings(file) {
+            let (severity, source) = match finding.is_error {
+                true => ("error", "sleek.error"),
+                false => ("warning", "sleek.unformatted"),
+            };
+            let line = finding.lines.map_or(1, |lines| lines.start);
+            let _ = writeln!(
+                xml,
+                "    <error line=\"{line}\" severity=\"{severity}\" message=\"{}\" source=\"{source}\"/>",
+                escape(&finding.message)
+            );
+        }
+        xml.push_str("  </file>\n");
+    }
+
+    xml.push_str("</checkstyle>\n");
+    xml
+}
+
+/// Renders `files` as JUnit XML, with a test case for each file that fails if
+/// it is not formatted and errors if it could not be processed.
+pub fn to_junit(files: &[FileReport]) -> String {
+    let count = |status| files.iter().filter(|f| f.status == status).count();
+
+    let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    let _ = writeln!(
+        xml,
+        "<testsuites>\n  <testsuite name=\"sleek\" tests=\"{}\" failures=\"{}\" errors=\"{}\">",
+        files.len(),
+        count(Status::WouldReformat),
+        count(Status::Error)
+    );
+
+    for file in files {
+        let path = escape(&file.path);
+        let _ = write!(xml, "    <testcase name=\"{path}\" classname=\"sleek\"");
+        let messages: Vec<_> = findings(file)
+            .iter()
+            .map(|finding| escape(&finding.message))
+            .collect();
+
+        match file.status {
+            Status::WouldReformat => {
+                let _ = writeln!(
+                    xml,
+                    ">\n      <failure message=\"File is not formatted\">{}</failure>\n    </testcase>",
+                    messages.join("\n")
+                );
+            }
+            Status::Error => {
+                let _ = writeln!(
+                    xml,
+                    ">\n      <error message=\"{}\"/>\n    </testcase>",
+                    messages.join(" ")
+                );
+            }
+            Status::Unchanged | Status::Formatted => xml.push_str("/>\n"),
+        }
+    }
+
+    xml.push_str("  </testsuite>\n</testsuites>\n");
+    xml
+}
+
+/// Renders `files` as GitHub Actions workflow commands, with an error
+/// annotation for each finding.
+pub fn to_github(files: &[FileReport]) -> String {
+    let mut commands = String::new();
+    for finding in files.iter().flat_map(findings) {
+        let path = finding.file.path.replace('\\', "/");
+        let path = path.strip_prefix("./").unwrap_or(&path);
+        let _ = write!(commands, "::error file={}", escape_property(path));
+        if let Some(lines) = finding.lines {
+            let _ = write!(commands, ",line={},endLine={}", lines.start, lines.end);
+        }
+        let _ = writeln!(commands, "::{}", escape_data(&finding.message));
+    }
+    commands
+}
+
+/// Escapes the message of a workflow command.
+fn escape_data(text: &str) -> String {
+    text.replace('%', "%25")
+        .replace('\r', "%0D")
+        .replace('\n', "%0A")
+}
+
+/// Escapes a property value of a workflow command.
+fn escape_property(text: &str) -> String {
+    escape_data(text).replace(':', "%3A").replace(',', "%2C")
+}
+
+/// Escapes text for use in XML attributes and elements.
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            '\n' => escaped.push_str("&#10;"),
+            c if c.is_control() && c != '\t' => {}
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Converts a path to a relative URI reference, as SARIF expects.
+fn uri(path: &str) -> String {
+    let path = path.replace('\\', "/");
+    let path = path.strip_prefix("./").unwrap_or(&path);
+    let mut uri = String::with_capacity(path.len());
+    for byte in path.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' | b'/' => {
+                uri.push(char::from(byte))
+            }
+            byte => {
+                let _ = write!(uri, "%{byte:02X}");
+            }
+        }
+    }
+    uri
+}
@@ -0,0 +1,420 @@
+use crate::statements::{TokenKind, statement_end, statement_spans, token_at};
+use crate::{Config, Dialect, format_sql};
+
+/// Statements inside a PL/pgSQL body that are plain SQL, formatted as such.
+const SQL_STATEMENTS: &[&str] = &[
+    "ALTER", "CREATE", "DELETE", "DROP", "INSERT", "MERGE", "SELECT", "TRUNCATE", "UPDATE", "WITH",
+];
+
+/// PL/pgSQL keywords whose case follows [`Config::uppercase`], like SQL keywords.
+const PLPGSQL_KEYWORDS: &[&str] = &[
+    "ALIAS",
+    "AND",
+    "BEGIN",
+    "CASE",
+    "CONSTANT",
+    "CONTINUE",
+    "DEBUG",
+    "DECLARE",
+    "DEFAULT",
+    "DIAGNOSTICS",
+    "ELSE",
+    "ELSEIF",
+    "ELSIF",
+    "END",
+    "EXCEPTION",
+    "EXECUTE",
+    "EXIT",
+    "FALSE",
+    "FOR",
+    "FOREACH",
+    "GET",
+    "IF",
+    "IN",
+    "INFO",
+    "INTO",
+    "IS",
+    "LOG",
+    "LOOP",
+    "NEXT",
+    "NOT",
+    "NOTICE",
+    "NULL",
+    "OR",
+    "PERFORM",
+    "QUERY",
+    "RAISE",
+    "RETURN",
+    "REVERSE",
+    "SLICE",
+    "STRICT",
+    "THEN",
+    "TRUE",
+    "USING",
+    "WARNING",
+    "WHEN",
+    "WHILE",
+];
+
+/// Returns the language of the routine whose body is the dollar-quoted string
+/// starting at `body_start`, or `None` if the string is not a routine body.
+///
+/// The language comes from the statement's `LANGUAGE` clause, which may come
+/// before or after the body. A `DO` block without one is PL/pgSQL.
+pub(crate) fn body_language(
+    sql: &str,
+    body_start: usize,
+    dialect: Option<Dialect>,
+) -> Option<String> {
+    let span = statement_spans(sql, dialect)
+        .into_iter()
+        .find(|span| span.contains(&body_start))?;
+
+    let mut words = Vec::new();
+    let mut pos = span.start;
+    while pos < span.end {
+        let (kind, end) = token_at(sql, pos, dialect);
+        if matches!(
+            kind,
+            TokenKind::Word | TokenKind::Quoted | TokenKind::DollarQuoted
+        ) {
+            words.push(&sql[pos..end]);
+        }
+        pos = end;
+    }
+
+    match words
+        .iter()
+        .position(|w| w.eq_ignore_ascii_case("LANGUAGE"))
+    {
+        Some(i) => words
+            .get(i + 1)
+            .map(|language| language.trim_matches(['\'', '"']).to_lowercase()),
+        None => words
+            .first()
+            .filter(|word| word.eq_ignore_ascii_case("DO"))
+            .map(|_| "plpgsql".to_string()),
+    }
+}
+
+/// Formats a dollar-quoted routine body written in `language`, placing its
+/// content one level deeper than `indent` and the closing tag at `indent`.
+///
+/// Returns `None` for languages other than SQL and PL/pgSQL, whose bodies are
+/// kept as written.
+pub(crate) fn format_body(
+    quoted: &str,
+    language: &str,
+    indent: &str,
+    config: &Config,
+) -> Option<String> {
+    let tag = &quoted[..quoted[1..].find('$')? + 2];
+    let content = quoted[tag.len()..].strip_suffix(tag)?;
+    if content.trim().is_empty() {
+        return None;
+    }
+
+    let formatted = match language {
+        "sql" => format_sql(content.trim(), config),
+        "plpgsql" => format_plpgsql(content, config),
+        _ => return None,
+    };
+
+    let inner = format!("{indent}{}", " ".repeat(config.indent_spaces.into()));
+    let mut body = format!("{tag}\n");
+    for line in formatted.trim_end().lines() {
+        if !line.trim().is_empty() {
+            body.push_str(&inner);
+            body.push_str(line);
+        }
+        body.push('\n');
+    }
+    body.push_str(indent);
+    body.push_str(tag);
+    Some(body)
+}
+
+/// Formats PL/pgSQL, indenting the statements of each block, branch and loop.
+fn format_plpgsql(body: &str, config: &Config) -> String {
+    let mut formatter = PlPgSql {
+        config,
+        lines: Vec::new(),
+        stack: Vec::new(),
+    };
+
+    let mut pos = 0;
+    loop {
+        let rest = &body[pos..];
+        let gap = &rest[..rest.len() - rest.trim_start().len()];
+        pos += gap.len();
+        if pos == body.len() {
+            break;
+        }
+
+        // Keep blank lines that separate groups of statements
+        if gap.matches('\n').count() > 1 && !formatter.lines.is_empty() {
+            formatter.lines.push(String::new());
+        }
+        pos = formatter.item(body, pos);
+    }
+
+    formatter.lines.join("\n")
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum FrameKind {
+    /// The declarations before a block's `BEGIN`.
+    Declare,
+    Block,
+    If,
+    Loop,
+    Case,
+}
+
+/// An open block, branch or loop.
+#[derive(Debug, Clone, Copy)]
+struct Frame {
+    kind: FrameKind,
+    /// Indentation level of the lines that open and close it.
+    level: usize,
+    /// Indentation level of the statements it currently holds.
+    inner: usize,
+}
+
+struct PlPgSql<'a> {
+    config: &'a Config,
+    lines: Vec<String>,
+    stack: Vec<Frame>,
+}
+
+impl PlPgSql<'_> {
+    /// Formats the statement or block line starting at `pos`, returning its end.
+    fn item(&mut self, body: &str, pos: usize) -> usize {
+        let dialect = self.config.dialect;
+        let (kind, end) = token_at(body, pos, dialect);
+        let level = self.level();
+
+        if kind == TokenKind::Comment {
+            self.emit(level, body[pos..end].trim_end());
+            return end;
+        }
+        if body[pos..].starts_with("<<") {
+            let end = body[pos..].find(">>").map_or(body.len(), |i| pos + i + 2);
+            self.emit(level, &body[pos..end]);
+            return end;
+        }
+
+        let word = match kind {
+            TokenKind::Word => body[pos..end].to_ascii_uppercase(),
+            _ => String::new(),
+        };
+        let top = self.stack.last().copied();
+
+        match (word.as_str(), top.map(|frame| frame.kind)) {
+            ("DECLARE", _) => {
+                self.emit(level, &self.keyword("DECLARE"));
+                self.push(FrameKind::Declare, level);
+                end
+            }
+            ("BEGIN", Some(FrameKind::Declare)) => {
+                let frame = self.stack.last_mut().unwrap();
+                frame.kind = FrameKind::Block;
+                let level = frame.level;
+                self.emit(level, &self.keyword("BEGIN"));
+                end
+            }
+            ("BEGIN", _) => {
+                self.emit(level, &self.keyword("BEGIN"));
+                self.push(FrameKind::Block, level);
+                end
+            }
+            ("EXCEPTION", Some(FrameKind::Block)) => {
+                let frame = self.stack.last_mut().unwrap();
+                frame.inner = frame.level + 1;
+                let level = frame.level;
+                self.emit(level, &self.keyword("EXCEPTION"));
+                end
+            }
+            ("WHEN", Some(FrameKind::Block | FrameKind::Case)) => {
+                let end = find_word(body, pos, "THEN", dialect).map_or(body.len(), |(_, end)| end);
+                self.branch(body, pos..end)
+            }
+            ("ELSE", Some(FrameKind::Case)) => self.branch(body, pos..end),
+            ("ELSIF" | "ELSEIF", Some(FrameKind::If)) => {
+                let end = find_word(body, pos, "THEN", dialect).map_or(body.len(), |(_, end)| end);
+                let text = self.collapse(&body[pos..end]);
+                self.emit(top.unwrap().level, &text);
+                end
+            }
+            ("ELSE", Some(FrameKind::If)) => {
+                self.emit(top.unwrap().level, &self.keyword("ELSE"));
+                end
+            }
+            ("IF", _) => {
+                let end = find_word(body, pos, "THEN", dialect).map_or(body.len(), |(_, end)| end);
+                self.open(FrameKind::If, body, pos..end)
+            }
+            ("CASE", _) => {
+                let end =
+                    find_word(body, pos, "WHEN", dialect).map_or(body.len(), |(start, _)| start);
+                self.open(FrameKind::Case, body, pos..end)
+            }
+            ("LOOP", _) => self.open(FrameKind::Loop, body, pos..end),
+            ("WHILE" | "FOR" | "FOREACH", _) => {
+                let end = find_word(body, pos, "LOOP", dialect).map_or(body.len(), |(_, end)| end);
+                self.open(FrameKind::Loop, body, pos..end)
+            }
+            ("END", _) => {
+                let end = statement_end(&body[pos..], dialect).map_or(body.len(), |e| pos + e);
+                let level = self.stack.pop().map_or(level, |frame| frame.level);
+                let text = self.collapse(&body[pos..end]);
+                self.emit(level, &text);
+                end
+            }
+            _ => {
+                let end = statement_end(&body[pos..], dialect).map_or(body.len(), |e| pos + e);
+                let text = self.statement(&body[pos..end]);
+                self.emit(level, &text);
+                end
+            }
+        }
+    }
+
+    /// Formats a statement, as SQL if it is one.
+    fn statement(&self, text: &str) -> String {
+        let mut words = text.split_whitespace();
+        let first = words.next().unwrap_or_default().to_ascii_uppercase();
+        let is_sql = |word: Option<&str>| {
+            word.is_some_and(|word| SQL_STATEMENTS.contains(&word.to_ascii_uppercase().as_str()))
+        };
+
+        if is_sql(Some(&first)) {
+            return format_sql(text.trim(), self.config);
+        }
+
+        // The query of `RETURN QUERY` goes on its own lines
+        let second = words.next();
+        if first == "RETURN" && second.is_some_and(|w| w.eq_ignore_ascii_case("QUERY")) {
+            if let Some(query_start) = text.to_ascii_uppercase().find("QUERY") {
+                let query = text[query_start + "QUERY".len()..].trim();
+                if is_sql(query.split_whitespace().next()) {
+                    let query = format_sql(query, self.config);
+                    return format!("{}\n{query}", self.keyword("RETURN QUERY"));
+                }
+            }
+        }
+
+        self.collapse(text)
+    }
+
+    /// Emits a block header such as `IF ... THEN` and opens its block.
+    fn open(&mut self, kind: FrameKind, body: &str, header: std::ops::Range<usize>) -> usize {
+        let level = self.level();
+        let text = self.collapse(&body[header.clone()]);
+        self.emit(level, &text);
+        self.push(kind, level);
+        header.end
+    }
+
+    /// Emits a `WHEN ... THEN` or `ELSE` branch of a `CASE` or exception handler.
+    fn branch(&mut self, body: &str, header: std::ops::Range<usize>) -> usize {
+        let frame = self.stack.last_mut().unwrap();
+        frame.inner = frame.level + 2;
+        let level = frame.level + 1;
+        let text = self.collapse(&body[header.clone()]);
+        self.emit(level, &text);
+        header.end
+    }
+
+    fn push(&mut self, kind: FrameKind, level: usize) {
+        self.stack.push(Frame {
+            kind,
+            level,
+            inner: level + 1,
+        });
+    }
+
+    /// Returns the indentation level of statements in the innermost open block.
+    fn level(&self) -> usize {
+        self.stack.last().map_or(0, |frame| frame.inner)
+    }
+
+    fn emit(&mut self, level: usize, text: &str) {
+        let indent = " ".repeat(usize::from(self.config.indent_spaces) * level);
+        for line in text.lines() {
+            self.lines.push(match line.trim().is_empty() {
+                true => String::new(),
+                false => format!("{indent}{line}"),
+            });
+        }
+    }
+
+    fn keyword(&self, keyword: &str) -> String {
+        match self.config.uppercase {
+            true => keyword.to_uppercase(),
+            false => keyword.to_lowercase(),
+        }
+    }
+
+    /// Joins `text` onto one line, collapsing whitespace outside of strings and
+    /// comments and setting the case of PL/pgSQL keywords.
+    fn collapse(&self, text: &str) -> String {
+        let mut collapsed = String::new();
+        let mut pos = 0;
+
+        while pos < text.len() {
+            let (kind, end) = token_at(text, pos, self.config.dialect);
+            let token = &text[pos..end];
+            match kind {
+                TokenKind::Other if token.trim().is_empty() => {
+                    if !collapsed.is_empty() && !collapsed.ends_with(['\n', ' ']) {
+                        collapsed.push(' ');
+                    }
+                }
+                // A line comment runs to the end of its line
+                TokenKind::Comment if token.starts_with("--") || token.starts_with('#') => {
+                    collapsed.push_str(token.trim_end());
+                    collapsed.push('\n');
+                }
+                TokenKind::Word
+                    if PLPGSQL_KEYWORDS.contains(&token.to_ascii_uppercase().as_str()) =>
+                {
+                    collapsed.push_str(&self.keyword(token));
+                }
+                _ => collapsed.push_str(token),
+            }
+            pos = end;
+        }
+
+        collapsed.trim().to_string()
+    }
+}
+
+/// Finds the next `word` in `text` after `start`, outside of parentheses,
+/// returning its start and end.
+fn find_word(
+    text: &str,
+    start: usize,
+    word: &str,
+    dialect: Option<Dialect>,
+) -> Option<(usize, usize)> {
+    let mut depth = 0usize;
+    let mut pos = start;
+
+    while pos < text.len() {
+        let (kind, end) = token_at(text, pos, dialect);
+        match (kind, &text[pos..end]) {
+            (TokenKind::Other, "(") => depth += 1,
+            (TokenKind::Other, ")") => depth = depth.saturating_sub(1),
+            (TokenKind::Word, token)
+                if depth == 0 && pos > start && token.eq_ignore_ascii_case(word) =>
+            {
+                return Some((pos, end));
+            }
+            _ => {}
+        }
+        pos = end;
+    }
+
+    None
+}
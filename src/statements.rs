@@ -0,0 +1,215 @@
+use std::ops::Range;
+
+use crate::Dialect;
+
+/// The kind of a lexical token in SQL source.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TokenKind {
+    /// A string literal or quoted identifier, including any prefix such as `N`.
+    Quoted,
+    /// A dollar-quoted string such as `$$text$$` or `$tag$text$tag$`.
+    DollarQuoted,
+    /// A line or block comment.
+    Comment,
+    /// A run of letters, digits and underscores.
+    Word,
+    /// Any other single character, such as whitespace, operators and punctuation.
+    Other,
+}
+
+/// Returns the kind and end offset of the token starting at `start` in `sql`.
+///
+/// Without a dialect, `'`, `"` and `` ` `` quote with doubled-quote escapes and
+/// only `--` and `/* */` start comments. A dialect adds its own quoting rules,
+/// such as backslash escapes, string prefixes and `[bracketed]` identifiers.
+pub fn token_at(sql: &str, start: usize, dialect: Option<Dialect>) -> (TokenKind, usize) {
+    let bytes = sql.as_bytes();
+    let next = bytes.get(start + 1).copied();
+
+    match bytes[start] {
+        b'-' if next == Some(b'-') => (TokenKind::Comment, skip_line_comment(bytes, start)),
+        b'/' if next == Some(b'*') => (TokenKind::Comment, skip_block_comment(bytes, start)),
+        b'#' if dialect.is_some_and(Dialect::hash_comments) => {
+            (TokenKind::Comment, skip_line_comment(bytes, start))
+        }
+        b'\'' | b'"' => (TokenKind::Quoted, skip_string(bytes, start, dialect, None)),
+        b'`' if dialect.is_none_or(Dialect::backtick_identifiers) => {
+            (TokenKind::Quoted, skip_quoted(bytes, start, b'`', false))
+        }
+        b'[' if dialect.is_some_and(Dialect::bracket_identifiers) => {
+            (TokenKind::Quoted, skip_quoted(bytes, start, b']', false))
+        }
+        b'$' if dialect.is_none_or(Dialect::dollar_quoted_strings) => {
+            match skip_dollar_quoted(sql, start) {
+                Some(end) => (TokenKind::DollarQuoted, end),
+                None => (TokenKind::Other, start + 1),
+            }
+        }
+        b if is_word_byte(b) => {
+            // `$` may appear inside identifiers, as in `tbl$1`
+            let end = start
+                + bytes[start..]
+                    .iter()
+                    .position(|&b| !is_word_byte(b) && b != b'$')
+                    .unwrap_or(bytes.len() - start);
+
+            match dialect.and_then(|d| d.string_prefix(&sql[start..end], &bytes[end..])) {
+                Some(prefix) => {
+                    let quote = start + prefix.len;
+                    let end = skip_string(bytes, quote, dialect, prefix.backslash);
+                    (TokenKind::Quoted, end)
+                }
+                None => (TokenKind::Word, end),
+            }
+        }
+        _ => {
+            let width = sql[start..].chars().next().map_or(1, char::len_utf8);
+            (TokenKind::Other, start + width)
+        }
+    }
+}
+
+fn is_word_byte(b: u8) -> bool {
+    b.is_ascii_alphanumeric() || b == b'_' || !b.is_ascii()
+}
+
+/// Returns the end of the dollar-quoted string starting at `start`, or `None` if
+/// the `$` there does not open one, as in the parameter `$1`.
+fn skip_dollar_quoted(sql: &str, start: usize) -> Option<usize> {
+    let tag_len = sql[start + 1..].find('$')?;
+    let tag = &sql[start..start + tag_len + 2];
+    let name = &tag[1..tag.len() - 1];
+    let valid = name
+        .chars()
+        .enumerate()
+        .all(|(i, c)| c == '_' || c.is_alphabetic() || (i > 0 && c.is_ascii_digit()));
+    if !valid {
+        return None;
+    }
+
+    let body = start + tag.len();
+    Some(
+        sql[body..]
+            .find(tag)
+            .map_or(sql.len(), |offset| body + offset + tag.len()),
+    )
+}
+
+/// Skips a string literal or quoted identifier starting with the `'` or `"` at
+/// `start`, following the escaping rules of `dialect` unless a string prefix
+/// decided whether `backslash` escapes apply.
+fn skip_string(
+    bytes: &[u8],
+    start: usize,
+    dialect: Option<Dialect>,
+    backslash: Option<bool>,
+) -> usize {
+    let quote = bytes[start];
+    let Some(dialect) = dialect else {
+        return skip_quoted(bytes, start, quote, false);
+    };
+
+    if dialect.triple_quoted_strings() && bytes[start..].starts_with(&[quote; 3]) {
+        let body = start + 3;
+        return bytes[body..]
+            .windows(3)
+            .position(|window| window == [quote; 3])
+            .map_or(bytes.len(), |offset| body + offset + 3);
+    }
+
+    let is_string = quote == b'\'' || dialect.double_quoted_strings();
+    let backslash = is_string && backslash.unwrap_or(dialect.backslash_escapes());
+    skip_quoted(bytes, start, quote, backslash)
+}
+
+/// Skips quoted text starting at `start` and closed by `close`, where a doubled
+/// closing character is an escaped one and, if `backslash` is set, so is any
+/// character following a backslash.
+fn skip_quoted(bytes: &[u8], start: usize, close: u8, backslash: bool) -> usize {
+    let mut i = start + 1;
+    while i < bytes.len() {
+        if backslash && bytes[i] == b'\\' {
+            i += 2;
+            continue;
+        }
+        if bytes[i] == close {
+            if bytes.get(i + 1) == Some(&close) {
+                i += 2;
+                continue;
+            }
+            return i + 1;
+        }
+        i += 1;
+    }
+    bytes.len()
+}
+
+fn skip_line_comment(bytes: &[u8], start: usize) -> usize {
+    bytes[start..]
+        .iter()
+        .position(|&b| b == b'\n')
+        .map_or(bytes.len(), |offset| start + offset + 1)
+}
+
+fn skip_block_comment(bytes: &[u8], start: usize) -> usize {
+    bytes[start + 2..]
+        .windows(2)
+        .position(|window| window == b"*/")
+        .map_or(bytes.len(), |offset| start + 2 + offset + 2)
+}
+
+/// Returns the byte offset just past the first top-level `;` in `sql`.
+///
+/// Semicolons inside string literals, quoted identifiers and comments are
+/// ignored. Returns `None` if the statement is not terminated.
+pub fn statement_end(sql: &str, dialect: Option<Dialect>) -> Option<usize> {
+    let mut i = 0;
+
+    while i < sql.len() {
+        let (kind, end) = token_at(sql, i, dialect);
+        if kind == TokenKind::Other && sql.as_bytes()[i] == b';' {
+            return Some(end);
+        }
+        i = end;
+    }
+
+    None
+}
+
+/// Splits `sql` into the byte ranges of its statements.
+///
+/// Each range starts at the statement's first non-whitespace character, which
+/// may be a leading comment, and ends just past its terminating semicolon. A
+/// trailing unterminated statement ends at its last non-whitespace character.
+pub fn statement_spans(sql: &str, dialect: Option<Dialect>) -> Vec<Range<usize>> {
+    let mut spans = Vec::new();
+    let mut pos = 0;
+
+    loop {
+        let rest = &sql[pos..];
+        let start = pos + (rest.len() - rest.trim_start().len());
+        if start == sql.len() {
+            break;
+        }
+
+        match statement_end(&sql[start..], dialect) {
+            Some(end) => {
+                spans.push(start..start + end);
+                pos = start + end;
+            }
+            None => {
+                spans.push(start..sql.trim_end().len());
+                break;
+            }
+        }
+    }
+
+    spans
+}
+
+/// Returns the offset just past the end of the line containing `pos`.
+pub fn next_line(input: &str, pos: usize) -> usize {
+    input[pos..]
+        .find('\n')
+        .map_or(input.len(), |offset| pos + offset + 1)
+}
@@ -0,0 +1,294 @@
+use std::fmt;
+
+use clap::ValueEnum;
+
+use crate::directives::{self, Segment};
+use crate::statements::{TokenKind, token_at};
+use crate::{Dialect, Language, markdown};
+
+/// Characters of a token shown in a [`Mismatch`].
+const MAX_TOKEN_CHARS: usize = 40;
+
+/// Characters that make up operators such as `->>`, `::` and `<=`.
+const OPERATOR_CHARS: &[u8] = b"+-*/<>=~!@#%^&|?:";
+
+/// Characters an operator may end in only if it also holds one of these,
+/// following Postgres, so `=-1` is read as `=` and `-1`.
+const OPERATOR_ONLY_CHARS: &[u8] = b"~!@#%^&|?";
+
+/// The first token that differs between the original and formatted text.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Mismatch {
+    /// 1-based line of the token in the original text.
+    pub line: usize,
+    /// 1-based column, in characters, of the token in the original text.
+    pub column: usize,
+    /// The original token, or `end of input`.
+    pub expected: String,
+    /// The formatted token, or `end of input`.
+    pub found: String,
+}
+
+impl fmt::Display for Mismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "line {}, column {}: expected {}, found {}",
+            self.line, self.column, self.expected, self.found
+        )
+    }
+}
+
+/// Checks that `formatted` has the same tokens as `original`.
+///
+/// Whitespace between tokens and inside comments is ignored, as is the case of
+/// unquoted words, since keywords are converted to upper or lower case. Strings,
+/// quoted identifiers and everything else must match exactly, with operators
+/// such as `->>` and `::` and prefixed names such as `@p`, `@@version`, `:name`
+/// and `$1` compared whole, so splitting them is caught. The bodies of
+/// dollar-quoted strings are compared token by token, as they may hold
+/// formatted function bodies. In Markdown, only SQL code blocks are compared.
+///
+/// Without a dialect, the text may be read with any one dialect's quoting
+/// rules, since input mixing common dialects is formatted the same way.
+pub fn verify(
+    original: &str,
+    formatted: &str,
+    language: Language,
+    dialect: Option<Dialect>,
+) -> Result<(), Mismatch> {
+    let result = compare(original, formatted, language, dialect);
+    match (result, dialect) {
+        (Err(mismatch), None) => {
+            let mut dialects = Dialect::value_variants().iter();
+            match dialects.any(|&d| compare(original, formatted, language, Some(d)).is_ok()) {
+                true => Ok(()),
+                false => Err(mismatch),
+            }
+        }
+        (result, _) => result,
+    }
+}
+
+fn compare(
+    original: &str,
+    formatted: &str,
+    language: Language,
+    dialect: Option<Dialect>,
+) -> Result<(), Mismatch> {
+    let expected = document_tokens(original, language, dialect);
+    let found = document_tokens(formatted, language, dialect);
+
+    let mut found = found.iter();
+    for token in &expected {
+        match found.next() {
+            Some(other) if token.matches(other) => {}
+            other => return Err(mismatch(original, Some(token), other)),
+        }
+    }
+    match found.next() {
+        Some(other) => Err(mismatch(original, None, Some(other))),
+        None => Ok(()),
+    }
+}
+
+fn mismatch(original: &str, expected: Option<&Token>, found: Option<&Token>) -> Mismatch {
+    let offset = expected.map_or(original.len(), |token| token.offset);
+    let line_start = original[..offset].rfind('\n').map_or(0, |i| i + 1);
+    let describe = |token: Option<&Token>| match token {
+        Some(token) => {
+            // Strings and comments may be long, so only show their start
+            let line = token.text.lines().next().unwrap_or_default();
+            match line.char_indices().nth(MAX_TOKEN_CHARS) {
+                Some((end, _)) => format!("`{}...`", &line[..end]),
+                None if line.len() < token.text.len() => format!("`{line}...`"),
+                None => format!("`{line}`"),
+            }
+        }
+        None => "end of input".to_string(),
+    };
+
+    Mismatch {
+        line: original[..offset].matches('\n').count() + 1,
+        column: original[line_start..offset].chars().count() + 1,
+        expected: describe(expected),
+        found: describe(found),
+    }
+}
+
+/// A token other than whitespace, at a byte offset into the whole document.
+struct Token<'a> {
+    kind: TokenKind,
+    offset: usize,
+    text: &'a str,
+}
+
+impl Token<'_> {
+    fn matches(&self, other: &Token) -> bool {
+        if self.kind != other.kind {
+            return false;
+        }
+        match self.kind {
+            TokenKind::Word => self.text.eq_ignore_ascii_case(other.text),
+            TokenKind::Comment => self
+                .text
+                .split_whitespace()
+                .eq(other.text.split_whitespace()),
+            _ => self.text == other.text,
+        }
+    }
+}
+
+fn document_tokens(text: &str, language: Language, dialect: Option<Dialect>) -> Vec<Token<'_>> {
+    let mut tokens = Vec::new();
+    match language {
+        Language::Sql => sql_tokens(text, 0, dialect, &mut tokens),
+        Language::Markdown => {
+            for block in markdown::sql_blocks(text) {
+                sql_tokens(&text[block.clone()], block.start, dialect, &mut tokens);
+            }
+        }
+    }
+    tokens
+}
+
+/// Appends the tokens of `sql`, which starts at `base` in the document,
+/// splitting statements on any `DELIMITER` the way formatting does.
+fn sql_tokens<'a>(
+    sql: &'a str,
+    base: usize,
+    dialect: Option<Dialect>,
+    tokens: &mut Vec<Token<'a>>,
+) {
+    for segment in directives::segments(sql, dialect) {
+        match segment {
+            Segment::Format(text) | Segment::Verbatim(text) => {
+                lex(text, base + offset_in(sql, text), dialect, tokens);
+            }
+            Segment::Delimited { text, delimiter } => {
+                for (statement, terminated) in directives::split_delimited(text, delimiter, dialect)
+                {
+                    let offset = base + offset_in(sql, statement);
+                    lex(statement, offset, dialect, tokens);
+                    if terminated {
+                        tokens.push(Token {
+                            kind: TokenKind::Other,
+                            offset: offset + statement.len(),
+                            text: delimiter,
+                        });
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn lex<'a>(sql: &'a str, base: usize, dialect: Option<Dialect>, tokens: &mut Vec<Token<'a>>) {
+    let mut pos = 0;
+    while pos < sql.len() {
+        let (kind, end) = match token_at(sql, pos, dialect) {
+            (TokenKind::Other, end) if end == pos + 1 => {
+                (TokenKind::Other, operator_end(sql, pos, dialect))
+            }
+            token => token,
+        };
+        let text = &sql[pos..end];
+
+        match kind {
+            TokenKind::Other if text.trim().is_empty() => {}
+            TokenKind::DollarQuoted => {
+                let tag_len = text[1..].find('$').map_or(text.len(), |i| i + 2);
+                let tag = &text[..tag_len];
+                // An unterminated string runs to the end of the input
+                let closed = text.len() >= 2 * tag_len && text.ends_with(tag);
+                let body_end = match closed {
+                    true => text.len() - tag_len,
+                    false => text.len(),
+                };
+
+                tokens.push(Token {
+                    kind,
+                    offset: base + pos,
+                    text: tag,
+                });
+                lex(
+                    &text[tag_len..body_end],
+                    base + pos + tag_len,
+                    dialect,
+                    tokens,
+                );
+                if closed {
+                    tokens.push(Token {
+                        kind,
+                        offset: base + pos + body_end,
+                        text: tag,
+                    });
+                }
+            }
+            kind => tokens.push(Token {
+                kind,
+                offset: base + pos,
+                text,
+            }),
+        }
+        pos = end;
+    }
+}
+
+/// Returns the end of the operator or prefixed name starting at `start`, so
+/// that splitting `->>` or `@p` apart is not mistaken for a change of
+/// whitespace.
+fn operator_end(sql: &str, start: usize, dialect: Option<Dialect>) -> usize {
+    if let Some(end) = prefixed_name_end(sql, start, dialect) {
+        return end;
+    }
+
+    let bytes = sql.as_bytes();
+    let is_operator_char = |pos: usize| {
+        pos < sql.len()
+            && OPERATOR_CHARS.contains(&bytes[pos])
+            && token_at(sql, pos, dialect) == (TokenKind::Other, pos + 1)
+    };
+    let mut end = start;
+    while is_operator_char(end) && (end == start || prefixed_name_end(sql, end, dialect).is_none())
+    {
+        end += 1;
+    }
+
+    let operator_only = bytes[start..end]
+        .iter()
+        .any(|b| OPERATOR_ONLY_CHARS.contains(b));
+    while end > start + 1 && matches!(bytes[end - 1], b'+' | b'-') && !operator_only {
+        end -= 1;
+    }
+    end.max(start + 1)
+}
+
+/// Returns the end of a name prefixed by a sigil at `start`, such as the
+/// variables `@p` and `@@version`, the parameters `:name` and `$1` or the
+/// temporary table `#temp`.
+fn prefixed_name_end(sql: &str, start: usize, dialect: Option<Dialect>) -> Option<usize> {
+    let bytes = sql.as_bytes();
+    let sigil = bytes[start];
+    let max_len = match sigil {
+        b'@' | b'#' => 2,
+        // `::` is a cast
+        b':' if start == 0 || bytes[start - 1] != b':' => 1,
+        b'$' => 1,
+        _ => return None,
+    };
+    let len = bytes[start..].iter().take_while(|&&b| b == sigil).count();
+    let name = start + len;
+    if len > max_len || name >= sql.len() {
+        return None;
+    }
+    match token_at(sql, name, dialect) {
+        (TokenKind::Word, end) if sigil != b'$' || bytes[name].is_ascii_digit() => Some(end),
+        _ => None,
+    }
+}
+
+/// Returns the byte offset of `inner`, a subslice of `outer`, within it.
+fn offset_in(outer: &str, inner: &str) -> usize {
+    inner.as_ptr() as usize - outer.as_ptr() as usize
+}
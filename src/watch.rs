@@ -0,0 +1,79 @@
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::time::Duration;
+
+use notify::event::EventKind;
+use notify::{Event, RecommendedWatcher, RecursiveMode, Watcher as _};
+
+use crate::Error;
+
+/// How long the files must stay unchanged before changes are reported, so an
+/// editor saving in several steps only triggers one format.
+pub const DEBOUNCE: Duration = Duration::from_millis(200);
+
+/// Watches files and directories for changes, using inotify on Linux.
+pub struct Watcher {
+    // Dropping the watcher stops the events
+    _watcher: RecommendedWatcher,
+    events: Receiver<notify::Result<Event>>,
+}
+
+impl Watcher {
+    /// Watches each directory in `paths` recursively, and the directory holding
+    /// each file so that files replaced by a rename are still seen.
+    pub fn new(paths: &[PathBuf]) -> Result<Self, Error> {
+        let (sender, events) = mpsc::channel();
+        let mut watcher = notify::recommended_watcher(sender)?;
+
+        let mut watched = BTreeSet::new();
+        for path in paths {
+            let (target, mode) = match path.is_dir() {
+                true => (path.as_path(), RecursiveMode::Recursive),
+                false => match path.parent() {
+                    Some(parent) if !parent.as_os_str().is_empty() => {
+                        (parent, RecursiveMode::NonRecursive)
+                    }
+                    _ => (Path::new("."), RecursiveMode::NonRecursive),
+                },
+            };
+            if watched.insert((target.to_path_buf(), mode == RecursiveMode::Recursive)) {
+                watcher.watch(target, mode)?;
+            }
+        }
+
+        Ok(Self {
+            _watcher: watcher,
+            events,
+        })
+    }
+
+    /// Blocks until files are created or modified, then returns their paths
+    /// once nothing has changed for [`DEBOUNCE`].
+    pub fn changes(&self) -> Result<Vec<PathBuf>, Error> {
+        let mut changed = BTreeSet::new();
+        loop {
+            let event = match changed.is_empty() {
+                true => self
+                    .events
+                    .recv()
+                    .map_err(|_| RecvTimeoutError::Disconnected),
+                false => self.events.recv_timeout(DEBOUNCE),
+            };
+
+            match event {
+                Ok(event) => {
+                    let event = event?;
+                    if matches!(
+                        event.kind,
+                        EventKind::Create(_) | EventKind::Modify(_) | EventKind::Any
+                    ) {
+                        changed.extend(event.paths);
+                    }
+                }
+                Err(RecvTimeoutError::Timeout) => return Ok(changed.into_iter().collect()),
+                Err(RecvTimeoutError::Disconnected) => return Err(Error::WatchStopped),
+            }
+        }
+    }
+}
@@ -0,0 +1,12 @@
+-- Monthly totals
+select /* inline */ month, -- trailing comment
+  sum(total)
+from sales
+/* block
+   comment */
+group by month;
+
+-- sleek: skip
+select   keep,   as_is from t;
+
+select 'it''s', "quoted ""id""", e'esc\n' from dual;
@@ -0,0 +1,9 @@
+create table if not exists accounts (
+  id bigserial primary key,
+  email varchar(255) not null unique,
+  created_at timestamptz not null default now(),
+  balance numeric(12,2) check (balance >= 0)
+);
+create index accounts_email_idx on accounts (lower(email));
+alter table accounts add column deleted_at timestamptz;
+drop table if exists legacy_accounts cascade;
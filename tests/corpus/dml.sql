@@ -0,0 +1,4 @@
+insert into accounts (email, balance) values ('a@example.com', 10), ('b@example.com', 20) on conflict (email) do update set balance = excluded.balance returning id;
+update accounts set balance = balance - 5, updated_at = now() where id = 1;
+delete from sessions where expires_at < now();
+merge into target t using source s on t.id = s.id when matched then update set v = s.v when not matched then insert (id, v) values (s.id, s.v);
@@ -0,0 +1,8 @@
+with recent as (select account_id, sum(amount) total from payments where paid_at > now() - interval '30 days' group by account_id having sum(amount) > 100)
+select a.id, a.email, r.total, rank() over (partition by a.region order by r.total desc) as position
+from accounts a join recent r on r.account_id = a.id left join regions g on g.id = a.region
+where a.deleted_at is null and (a.email like '%@example.com' or a.id in (1, 2, 3))
+order by r.total desc limit 10 offset 20;
+select case when x > 0 then 'positive' when x < 0 then 'negative' else 'zero' end as sign from numbers;
+select id from a union all select id from b except select id from c;
+select count(*) filter (where ok) from checks;
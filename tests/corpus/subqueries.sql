@@ -0,0 +1,3 @@
+select * from (select id, (select max(v) from w where w.id = t.id) as m from t where exists (select 1 from u where u.t_id = t.id)) sub where m between 1 and 10;
+select coalesce(a, b, c), cast(x as integer), x::text, extract(year from d) from t;
+select distinct on (a) a, b from t order by a, b desc nulls last;
@@ -0,0 +1,80 @@
+use std::fs;
+use std::path::Path;
+
+use sleek::{Config, Dialect, format_str};
+
+/// Formats each `<name>.sql` in `tests/golden/<dir>` as `dialect` and compares
+/// it with `<name>.expected.sql`, which must also be stable when formatted again.
+fn check_golden_files(dialect: Dialect, dir: &str) {
+    let mut config = Config::default();
+    config.dialect = Some(dialect);
+
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/golden")
+        .join(dir);
+    let mut checked = 0;
+
+    for entry in fs::read_dir(&dir).unwrap() {
+        let path = entry.unwrap().path();
+        let name = path.file_name().unwrap().to_str().unwrap();
+        let Some(stem) = name.strip_suffix(".sql") else {
+            continue;
+        };
+        if stem.ends_with(".expected") {
+            continue;
+        }
+
+        let input = fs::read_to_string(&path).unwrap();
+        let expected = fs::read_to_string(dir.join(format!("{stem}.expected.sql"))).unwrap();
+
+        assert_eq!(
+            format_str(&input, &config).unwrap(),
+            expected,
+            "{} formatted differently",
+            path.display()
+        );
+        assert_eq!(
+            format_str(&expected, &config).unwrap(),
+            expected,
+            "{stem}.expected.sql is not stable"
+        );
+        checked += 1;
+    }
+
+    assert!(checked > 0, "no golden files in {}", dir.display());
+}
+
+#[test]
+fn test_postgres_golden_files() {
+    check_golden_files(Dialect::Postgres, "postgres");
+}
+
+#[test]
+fn test_mysql_golden_files() {
+    check_golden_files(Dialect::Mysql, "mysql");
+}
+
+#[test]
+fn test_sqlite_golden_files() {
+    check_golden_files(Dialect::Sqlite, "sqlite");
+}
+
+#[test]
+fn test_tsql_golden_files() {
+    check_golden_files(Dialect::Tsql, "tsql");
+}
+
+#[test]
+fn test_bigquery_golden_files() {
+    check_golden_files(Dialect::Bigquery, "bigquery");
+}
+
+#[test]
+fn test_snowflake_golden_files() {
+    check_golden_files(Dialect::Snowflake, "snowflake");
+}
+
+#[test]
+fn test_ansi_golden_files() {
+    check_golden_files(Dialect::Ansi, "ansi");
+}
@@ -0,0 +1,9 @@
+SELECT
+    "Name",
+    count(*)
+FROM
+    t
+WHERE
+    x SIMILAR TO 'a%'
+GROUP BY
+    "Name";
@@ -0,0 +1 @@
+select "Name", count(*) from t where x similar to 'a%' group by "Name";
@@ -0,0 +1,12 @@
+SELECT
+    *
+FROM
+    a
+    CROSS JOIN LATERAL (
+        SELECT
+            *
+        FROM
+            b
+        WHERE
+            b.id = a.id
+    ) AS c;
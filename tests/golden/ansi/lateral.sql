@@ -0,0 +1 @@
+select * from a cross join lateral (select * from b where b.id = a.id) as c;
@@ -0,0 +1,10 @@
+SELECT
+    id,
+    STRUCT(a, b) AS pair
+FROM
+    t,
+    UNNEST(items) AS item QUALIFY row_number() over (
+        PARTITION by id
+        ORDER BY
+            ts DESC
+    ) = 1;
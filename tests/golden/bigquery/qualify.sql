@@ -0,0 +1,2 @@
+select id, struct(a, b) as pair from t, unnest(items) as item
+qualify row_number() over (partition by id order by ts desc) = 1;
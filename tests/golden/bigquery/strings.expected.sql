@@ -0,0 +1,7 @@
+SELECT
+    r'\d+' AS pattern,
+    b"bytes",
+    '''a;b''' AS multiline,
+    "it\"s"
+FROM
+    `project.dataset.table`;
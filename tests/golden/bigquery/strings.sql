@@ -0,0 +1 @@
+select r'\d+' as pattern, b"bytes", '''a;b''' as multiline, "it\"s" from `project.dataset.table`;
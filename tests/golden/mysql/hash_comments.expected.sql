@@ -0,0 +1,7 @@
+# report query
+SELECT
+    count(*)
+FROM
+    orders # all orders
+WHERE
+    state = 'open';
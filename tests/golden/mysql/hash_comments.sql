@@ -0,0 +1,3 @@
+# report query
+select count(*) from orders # all orders
+where state = 'open';
@@ -0,0 +1,14 @@
+SELECT
+    `order`,
+    `user id`,
+    "a;b" AS label,
+    'it\'s;' AS quote
+FROM
+    `my table`
+WHERE
+    x = 1;
+
+SELECT
+    2
+FROM
+    DUAL;
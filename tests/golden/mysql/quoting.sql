@@ -0,0 +1,2 @@
+select `order`, `user id`, "a;b" as label, 'it\'s;' as quote from `my table` where x = 1;
+select 2 from dual;
@@ -0,0 +1,18 @@
+SET
+    @total := 0;
+
+SELECT
+    @@session.sql_mode,
+    @@global.max_connections;
+
+SELECT
+    id,
+    @total := @total + amount
+FROM
+    orders
+WHERE
+    customer = @customer;
+
+SELECT
+    @'my var',
+    @`other var`;
@@ -0,0 +1,4 @@
+set @total := 0;
+select @@session.sql_mode, @@global.max_connections;
+select id, @total := @total+amount from orders where customer=@customer;
+select @'my var', @`other var`;
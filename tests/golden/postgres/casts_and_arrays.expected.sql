@@ -0,0 +1,10 @@
+SELECT
+    id::text,
+    tags::varchar[],
+    scores[1] AS first_score,
+    ARRAY[1, 2] AS pair
+FROM
+    event_log
+WHERE
+    payload #> '{user,name}' = $1
+    AND kind ILIKE 'a%';
@@ -0,0 +1,2 @@
+select id::text, tags::varchar[], scores[1] as first_score, ARRAY[1, 2] as pair
+from event_log where payload #> '{user,name}' = $1 and kind ilike 'a%';
@@ -0,0 +1,48 @@
+CREATE
+OR REPLACE FUNCTION add_ints(a int, b int) RETURNS int AS $$
+    SELECT
+        a + b;
+$$ LANGUAGE SQL;
+
+CREATE FUNCTION audit() RETURNS trigger LANGUAGE plpgsql AS $body$
+    DECLARE
+        n integer := 0;
+    BEGIN
+        IF new.amount > 100 THEN
+            INSERT INTO
+                audit_log (id, amount)
+            VALUES
+                (new.id, new.amount);
+        ELSIF new.amount < 0 THEN
+            RAISE EXCEPTION 'negative amount %', new.amount;
+        ELSE
+            n := n + 1;
+        END IF;
+        FOR r IN select * from items LOOP
+            UPDATE
+                items
+            SET
+                seen = TRUE
+            WHERE
+                id = r.id;
+        END LOOP;
+        RETURN new;
+    EXCEPTION
+        WHEN others THEN
+            RAISE NOTICE 'failed';
+            RETURN NULL;
+    END;
+$body$;
+
+CREATE FUNCTION py() RETURNS int AS $$
+  return   1
+$$ LANGUAGE plpython3u;
+
+DO $$
+    BEGIN
+        PERFORM pg_sleep(1);
+    END
+$$;
+
+SELECT
+    $$not a body; really$$ AS s;
@@ -0,0 +1,26 @@
+create or replace function add_ints(a int, b int) returns int as $$ select a+b; $$ language sql;
+create function audit() returns trigger language plpgsql as $body$
+declare
+  n integer := 0;
+begin
+  if new.amount > 100 then
+    insert into audit_log (id, amount) values (new.id, new.amount);
+  elsif new.amount < 0 then raise exception 'negative amount %', new.amount;
+  else
+    n := n + 1;
+  end if;
+  for r in select * from items loop
+    update items set seen = true where id = r.id;
+  end loop;
+  return new;
+exception
+  when others then
+    raise notice 'failed';
+    return null;
+end;
+$body$;
+create function py() returns int as $$
+  return   1
+$$ language plpython3u;
+do $$ begin perform pg_sleep(1); end $$;
+select $$not a body; really$$ as s;
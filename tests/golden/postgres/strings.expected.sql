@@ -0,0 +1,9 @@
+SELECT
+    'C:\' AS path,
+    E'it\'s' AS esc,
+    U&'d\0061t' AS unicode,
+    "Mixed Case"
+FROM
+    t
+WHERE
+    flags # 4 = 0;
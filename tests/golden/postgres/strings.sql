@@ -0,0 +1 @@
+select 'C:\' as path, E'it\'s' as esc, U&'d\0061t' as unicode, "Mixed Case" from t where flags # 4 = 0;
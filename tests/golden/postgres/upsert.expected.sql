@@ -0,0 +1,6 @@
+INSERT INTO
+    users (id, name)
+VALUES
+    ($1, $2) ON CONFLICT (id) DO NOTHING
+RETURNING
+    id;
@@ -0,0 +1 @@
+insert into users (id, name) values ($1, $2) on conflict (id) do nothing returning id;
@@ -0,0 +1,9 @@
+SELECT
+    v:name::STRING,
+    v:address.city,
+    v:tags[0],
+    "V":x
+FROM
+    raw
+WHERE
+    v:name ILIKE 'a%';
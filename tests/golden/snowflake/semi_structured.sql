@@ -0,0 +1 @@
+select v:name::string, v:address.city, v:tags[0], "V":x from raw where v:name ilike 'a%';
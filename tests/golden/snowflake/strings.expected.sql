@@ -0,0 +1,5 @@
+SELECT
+    'it\'s;' AS quote,
+    $1
+FROM
+    @stage;
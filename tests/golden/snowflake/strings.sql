@@ -0,0 +1 @@
+select 'it\'s;' as quote, $1 from @stage;
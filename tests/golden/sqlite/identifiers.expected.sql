@@ -0,0 +1,8 @@
+SELECT
+    [first name],
+    `last name`,
+    "email"
+FROM
+    [people]
+WHERE
+    x GLOB 'a*';
@@ -0,0 +1 @@
+select [first name], `last name`, "email" from [people] where x glob 'a*';
@@ -0,0 +1,3 @@
+PRAGMA foreign_keys = ON;
+
+CREATE TABLE t (id integer PRIMARY KEY AUTOINCREMENT, name text);
@@ -0,0 +1,2 @@
+pragma foreign_keys = on;
+create table t (id integer primary key autoincrement, name text);
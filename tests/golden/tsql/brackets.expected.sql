@@ -0,0 +1,9 @@
+SELECT
+    TOP 10 [order],
+    [user id],
+    [a]]b]
+FROM
+    [dbo].[orders] WITH (NOLOCK)
+WHERE
+    customer = @customer
+    AND name = N'abc';
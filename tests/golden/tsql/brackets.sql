@@ -0,0 +1 @@
+select top 10 [order], [user id], [a]]b] from [dbo].[orders] with (nolock) where customer = @customer and name = N'abc';
@@ -0,0 +1,12 @@
+SELECT
+    * INTO #recent
+FROM
+    orders
+WHERE
+    created > @since;
+
+SELECT
+    *
+FROM
+    #recent
+    JOIN ##shared ON #recent.id = ##shared.id;
@@ -0,0 +1,2 @@
+select * into #recent from orders where created > @since;
+select * from #recent join ##shared on #recent.id = ##shared.id;
@@ -0,0 +1,16 @@
+SELECT
+    @latest = max(created)
+FROM
+    orders;
+
+SELECT
+    id
+FROM
+    orders
+WHERE
+    created >= @since
+    AND STATUS = @Status;
+
+SELECT
+    @@version,
+    @@rowcount;
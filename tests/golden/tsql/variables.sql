@@ -0,0 +1,3 @@
+select @latest=max(created) from orders;
+select id from orders where created>=@since and status=@Status;
+select @@version, @@rowcount;
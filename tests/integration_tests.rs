@@ -1,14 +1,23 @@
 use std::fs;
 use std::io::Write;
+use std::path::Path;
 use std::process::{Command, Stdio};
 use tempfile::TempDir;
 
 fn sleek_command() -> Command {
-    Command::new(env!("CARGO_BIN_EXE_sleek"))
+    let mut command = Command::new(env!("CARGO_BIN_EXE_sleek"));
+    // Keep the output the same when the tests run in GitHub Actions
+    command.env_remove("GITHUB_ACTIONS");
+    command
 }
 
 fn run_sleek_with_stdin(args: &[&str], input: &[u8]) -> std::process::Output {
+    run_sleek_with_stdin_in(Path::new("."), args, input)
+}
+
+fn run_sleek_with_stdin_in(dir: &Path, args: &[&str], input: &[u8]) -> std::process::Output {
     let mut cmd = sleek_command();
+    cmd.current_dir(dir);
     for arg in args {
         cmd.arg(arg);
     }
@@ -21,7 +30,14 @@ fn run_sleek_with_stdin(args: &[&str], input: &[u8]) -> std::process::Output {
         .expect("Failed to start sleek");
 
     if let Some(stdin) = child.stdin.as_mut() {
-        stdin.write_all(input).expect("Failed to write to stdin");
+        // sleek may exit on invalid arguments before reading its input
+        if let Err(e) = stdin.write_all(input) {
+            assert_eq!(
+                e.kind(),
+                std::io::ErrorKind::BrokenPipe,
+                "Failed to write to stdin"
+            );
+        }
     }
 
     child.wait_with_output().expect("Failed to get output")
@@ -875,3 +891,2140 @@ fn test_error_message_quality() {
         );
     }
 }
+
+#[test]
+fn test_config_file_is_discovered_from_parent_directory() {
+    let temp_dir = TempDir::new().unwrap();
+    fs::create_dir(temp_dir.path().join(".git")).unwrap();
+    fs::write(
+        temp_dir.path().join("sleek.toml"),
+        "indent-spaces = 2\nuppercase = false\n",
+    )
+    .unwrap();
+
+    let nested_dir = temp_dir.path().join("migrations");
+    fs::create_dir(&nested_dir).unwrap();
+    let file_path = nested_dir.join("001.sql");
+    fs::write(&file_path, "select * from users").unwrap();
+
+    let output = sleek_command()
+        .arg(file_path.to_str().unwrap())
+        .output()
+        .expect("Failed to execute sleek");
+
+    assert!(output.status.success());
+    let formatted = fs::read_to_string(&file_path).unwrap();
+    assert_eq!(formatted, "select\n  *\nfrom\n  users\n");
+}
+
+#[test]
+fn test_cli_flags_override_config_file() {
+    let temp_dir = TempDir::new().unwrap();
+    fs::write(
+        temp_dir.path().join("sleek.toml"),
+        "indent-spaces = 2\nuppercase = false\n",
+    )
+    .unwrap();
+
+    let output = run_sleek_with_stdin_in(
+        temp_dir.path(),
+        &["--uppercase", "true"],
+        b"select * from users",
+    );
+
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert_eq!(stdout, "SELECT\n  *\nFROM\n  users\n");
+}
+
+#[test]
+fn test_nearest_config_file_wins() {
+    let temp_dir = TempDir::new().unwrap();
+    fs::write(temp_dir.path().join("sleek.toml"), "indent-spaces = 8\n").unwrap();
+
+    let nested_dir = temp_dir.path().join("nested");
+    fs::create_dir(&nested_dir).unwrap();
+    fs::write(nested_dir.join("sleek.toml"), "indent-spaces = 2\n").unwrap();
+
+    let output = run_sleek_with_stdin_in(&nested_dir, &[], b"select * from users");
+
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert_eq!(stdout, "SELECT\n  *\nFROM\n  users\n");
+}
+
+#[test]
+fn test_discovery_stops_at_repository_root() {
+    let temp_dir = TempDir::new().unwrap();
+    fs::write(temp_dir.path().join("sleek.toml"), "indent-spaces = 2\n").unwrap();
+
+    let repo_dir = temp_dir.path().join("repo");
+    fs::create_dir_all(repo_dir.join(".git")).unwrap();
+
+    let output = run_sleek_with_stdin_in(&repo_dir, &[], b"select * from users");
+
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert_eq!(stdout, "SELECT\n    *\nFROM\n    users\n");
+}
+
+#[test]
+fn test_pyproject_tool_sleek_table() {
+    let temp_dir = TempDir::new().unwrap();
+    fs::write(
+        temp_dir.path().join("pyproject.toml"),
+        "[project]\nname = \"example\"\n\n[tool.sleek]\nindent-spaces = 2\n",
+    )
+    .unwrap();
+
+    let output = run_sleek_with_stdin_in(temp_dir.path(), &[], b"select * from users");
+
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert_eq!(stdout, "SELECT\n  *\nFROM\n  users\n");
+}
+
+#[test]
+fn test_pyproject_without_tool_sleek_is_skipped() {
+    let temp_dir = TempDir::new().unwrap();
+    fs::write(temp_dir.path().join("sleek.toml"), "indent-spaces = 2\n").unwrap();
+
+    let nested_dir = temp_dir.path().join("nested");
+    fs::create_dir(&nested_dir).unwrap();
+    fs::write(
+        nested_dir.join("pyproject.toml"),
+        "[project]\nname = \"example\"\n",
+    )
+    .unwrap();
+
+    let output = run_sleek_with_stdin_in(&nested_dir, &[], b"select * from users");
+
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert_eq!(stdout, "SELECT\n  *\nFROM\n  users\n");
+}
+
+#[test]
+fn test_explicit_config_flag() {
+    let temp_dir = TempDir::new().unwrap();
+    fs::write(temp_dir.path().join("sleek.toml"), "indent-spaces = 8\n").unwrap();
+    let config_path = temp_dir.path().join("custom.toml");
+    fs::write(&config_path, "indent-spaces = 2\n").unwrap();
+
+    let output = run_sleek_with_stdin_in(
+        temp_dir.path(),
+        &["--config", config_path.to_str().unwrap()],
+        b"select * from users",
+    );
+
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert_eq!(stdout, "SELECT\n  *\nFROM\n  users\n");
+}
+
+#[test]
+fn test_no_config_flag_ignores_config_files() {
+    let temp_dir = TempDir::new().unwrap();
+    fs::write(temp_dir.path().join("sleek.toml"), "indent-spaces = 2\n").unwrap();
+
+    let output = run_sleek_with_stdin_in(temp_dir.path(), &["--no-config"], b"select * from users");
+
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert_eq!(stdout, "SELECT\n    *\nFROM\n    users\n");
+}
+
+#[test]
+fn test_invalid_config_file_reports_path() {
+    let temp_dir = TempDir::new().unwrap();
+    fs::write(temp_dir.path().join("sleek.toml"), "indent-size = 2\n").unwrap();
+
+    let output = run_sleek_with_stdin_in(temp_dir.path(), &[], b"select * from users");
+
+    assert!(!output.status.success());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("Failed to parse config file"));
+    assert!(stderr.contains("sleek.toml"));
+}
+
+#[test]
+fn test_check_mode_reports_every_unformatted_file() {
+    let temp_dir = TempDir::new().unwrap();
+
+    fs::write(temp_dir.path().join("a.sql"), "select * from users").unwrap();
+    fs::write(
+        temp_dir.path().join("b.sql"),
+        "SELECT\n    *\nFROM\n    users\n",
+    )
+    .unwrap();
+    fs::write(temp_dir.path().join("c.sql"), "select * from orders").unwrap();
+
+    let glob_pattern = format!("{}/*.sql", temp_dir.path().display());
+    let output = sleek_command()
+        .arg("--check")
+        .arg(&glob_pattern)
+        .output()
+        .expect("Failed to execute sleek");
+
+    assert!(!output.status.success());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("a.sql"), "should report the first file");
+    assert!(
+        !stderr.contains("b.sql"),
+        "should not report formatted files"
+    );
+    assert!(stderr.contains("c.sql"), "should report the last file");
+    assert!(stderr.contains("2 files are not formatted correctly"));
+
+    // Check mode must never modify files
+    assert_eq!(
+        fs::read_to_string(temp_dir.path().join("a.sql")).unwrap(),
+        "select * from users"
+    );
+}
+
+#[test]
+fn test_check_mode_processes_every_glob() {
+    let temp_dir = TempDir::new().unwrap();
+    let first = temp_dir.path().join("first.sql");
+    let second = temp_dir.path().join("second.sql");
+
+    fs::write(&first, "select 1").unwrap();
+    fs::write(&second, "select 2").unwrap();
+
+    let output = sleek_command()
+        .arg("--check")
+        .arg(first.to_str().unwrap())
+        .arg(second.to_str().unwrap())
+        .output()
+        .expect("Failed to execute sleek");
+
+    assert!(!output.status.success());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("first.sql"));
+    assert!(stderr.contains("second.sql"));
+}
+
+#[test]
+fn test_list_different_prints_only_paths() {
+    let temp_dir = TempDir::new().unwrap();
+    let unformatted = temp_dir.path().join("unformatted.sql");
+    let formatted = temp_dir.path().join("formatted.sql");
+
+    fs::write(&unformatted, "select * from users").unwrap();
+    fs::write(&formatted, "SELECT\n    *\nFROM\n    users\n").unwrap();
+
+    let glob_pattern = format!("{}/*.sql", temp_dir.path().display());
+    let output = sleek_command()
+        .arg("--list-different")
+        .arg(&glob_pattern)
+        .output()
+        .expect("Failed to execute sleek");
+
+    assert!(!output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert_eq!(stdout, format!("{}\n", unformatted.display()));
+    assert_eq!(
+        fs::read_to_string(&unformatted).unwrap(),
+        "select * from users",
+        "--list-different should not modify files"
+    );
+}
+
+#[test]
+fn test_list_different_succeeds_when_all_formatted() {
+    let temp_dir = TempDir::new().unwrap();
+    let file_path = temp_dir.path().join("formatted.sql");
+    fs::write(&file_path, "SELECT\n    *\nFROM\n    users\n").unwrap();
+
+    let output = sleek_command()
+        .arg("--list-different")
+        .arg(file_path.to_str().unwrap())
+        .output()
+        .expect("Failed to execute sleek");
+
+    assert!(output.status.success());
+    assert!(output.stdout.is_empty());
+}
+
+#[test]
+fn test_diff_flag_from_stdin() {
+    let output = run_sleek_with_stdin(&["--diff"], b"select * from users");
+
+    assert!(!output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert_eq!(
+        stdout,
+        "--- <stdin>\n+++ <stdin>\n@@ -1 +1,4 @@\n-select * from users\n\\ No newline at end of file\n+SELECT\n+    *\n+FROM\n+    users\n"
+    );
+}
+
+#[test]
+fn test_diff_flag_with_files_does_not_modify_them() {
+    let temp_dir = TempDir::new().unwrap();
+    let unformatted = temp_dir.path().join("unformatted.sql");
+    let formatted = temp_dir.path().join("formatted.sql");
+
+    fs::write(
+        &unformatted,
+        "SELECT\n    *\nFROM\n    users\nwhere id = 1\n",
+    )
+    .unwrap();
+    fs::write(&formatted, "SELECT\n    *\nFROM\n    users\n").unwrap();
+
+    let glob_pattern = format!("{}/*.sql", temp_dir.path().display());
+    let output = sleek_command()
+        .arg("--diff")
+        .arg(&glob_pattern)
+        .output()
+        .expect("Failed to execute sleek");
+
+    assert!(!output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let name = unformatted.display();
+    assert_eq!(
+        stdout,
+        format!(
+            "--- {name}\n+++ {name}\n@@ -2,4 +2,5 @@\n     *\n FROM\n     users\n-where id = 1\n+WHERE\n+    id = 1\n"
+        )
+    );
+    assert_eq!(
+        fs::read_to_string(&unformatted).unwrap(),
+        "SELECT\n    *\nFROM\n    users\nwhere id = 1\n",
+        "--diff should not modify files"
+    );
+}
+
+#[test]
+fn test_diff_flag_succeeds_when_formatted() {
+    let output = run_sleek_with_stdin(&["--diff"], b"SELECT\n    *\nFROM\n    users\n");
+
+    assert!(output.status.success());
+    assert!(output.stdout.is_empty());
+}
+
+#[test]
+fn test_diff_color_flag() {
+    // Output is piped, so auto should not colorize
+    let output = run_sleek_with_stdin(&["--diff"], b"select 1");
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(!stdout.contains('\x1b'), "auto should not colorize a pipe");
+
+    let output = run_sleek_with_stdin(&["--diff", "--color", "always"], b"select 1");
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.contains("\x1b[31m-select 1\x1b[0m"));
+    assert!(stdout.contains("\x1b[32m+SELECT\x1b[0m"));
+
+    let output = run_sleek_with_stdin(&["--diff", "--color", "never"], b"select 1");
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(!stdout.contains('\x1b'));
+}
+
+#[test]
+fn test_directory_argument_is_walked_recursively() {
+    let temp_dir = TempDir::new().unwrap();
+    let nested_dir = temp_dir.path().join("migrations").join("2024");
+    fs::create_dir_all(&nested_dir).unwrap();
+
+    let top = temp_dir.path().join("migrations").join("001.sql");
+    let nested = nested_dir.join("002.sql");
+    let other = nested_dir.join("notes.txt");
+    fs::write(&top, "select * from users").unwrap();
+    fs::write(&nested, "select * from orders").unwrap();
+    fs::write(&other, "select * from notes").unwrap();
+
+    let output = sleek_command()
+        .arg(temp_dir.path().join("migrations").to_str().unwrap())
+        .output()
+        .expect("Failed to execute sleek");
+
+    assert!(output.status.success());
+    assert!(fs::read_to_string(&top).unwrap().contains("SELECT"));
+    assert!(fs::read_to_string(&nested).unwrap().contains("SELECT"));
+    assert_eq!(
+        fs::read_to_string(&other).unwrap(),
+        "select * from notes",
+        "files without a .sql extension should be skipped"
+    );
+}
+
+#[test]
+fn test_directory_walk_honours_ignore_files() {
+    let temp_dir = TempDir::new().unwrap();
+    let root = temp_dir.path();
+    fs::create_dir_all(root.join("vendor")).unwrap();
+    fs::create_dir_all(root.join("generated")).unwrap();
+
+    fs::write(root.join(".gitignore"), "vendor/\n").unwrap();
+    fs::write(root.join(".sleekignore"), "generated/\n").unwrap();
+    fs::write(root.join("query.sql"), "select 1").unwrap();
+    fs::write(root.join("vendor").join("lib.sql"), "select 2").unwrap();
+    fs::write(root.join("generated").join("dump.sql"), "select 3").unwrap();
+
+    let output = sleek_command()
+        .arg("--check")
+        .arg(root.to_str().unwrap())
+        .output()
+        .expect("Failed to execute sleek");
+
+    assert!(!output.status.success());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("query.sql"));
+    assert!(!stderr.contains("lib.sql"), ".gitignore should be honoured");
+    assert!(
+        !stderr.contains("dump.sql"),
+        ".sleekignore should be honoured"
+    );
+    assert!(stderr.contains("1 file is not formatted correctly"));
+}
+
+#[test]
+fn test_exclude_path_pattern_when_walking_current_dir() {
+    let temp_dir = TempDir::new().unwrap();
+    let root = temp_dir.path();
+    fs::create_dir_all(root.join("vendor/sub")).unwrap();
+    fs::write(root.join("vendor/sub/a.sql"), "select 1").unwrap();
+    fs::write(root.join("query.sql"), "select 2").unwrap();
+
+    for args in [&["."][..], &["vendor", "query.sql"]] {
+        let output = sleek_command()
+            .current_dir(root)
+            .args(["--list-different", "--exclude", "vendor/**"])
+            .args(args)
+            .output()
+            .expect("Failed to execute sleek");
+
+        let stdout = String::from_utf8(output.stdout).unwrap();
+        assert!(stdout.contains("query.sql"), "{args:?}: {stdout}");
+        assert!(!stdout.contains("a.sql"), "{args:?}: {stdout}");
+    }
+}
+
+#[test]
+fn test_exclude_flag() {
+    let temp_dir = TempDir::new().unwrap();
+    let root = temp_dir.path();
+    fs::create_dir_all(root.join("vendor")).unwrap();
+
+    fs::write(root.join("query.sql"), "select 1").unwrap();
+    fs::write(root.join("query.generated.sql"), "select 2").unwrap();
+    fs::write(root.join("vendor").join("lib.sql"), "select 3").unwrap();
+
+    let output = sleek_command()
+        .args(["--exclude", "vendor", "--exclude", "*.generated.sql"])
+        .arg(root.to_str().unwrap())
+        .output()
+        .expect("Failed to execute sleek");
+
+    assert!(output.status.success());
+    assert!(
+        fs::read_to_string(root.join("query.sql"))
+            .unwrap()
+            .contains("SELECT")
+    );
+    assert_eq!(
+        fs::read_to_string(root.join("query.generated.sql")).unwrap(),
+        "select 2"
+    );
+    assert_eq!(
+        fs::read_to_string(root.join("vendor").join("lib.sql")).unwrap(),
+        "select 3"
+    );
+
+    // Excludes also apply to files matched by glob patterns
+    let glob_pattern = format!("{}/*.sql", root.display());
+    let output = sleek_command()
+        .args(["--check", "--exclude", "*.generated.sql"])
+        .arg(&glob_pattern)
+        .output()
+        .expect("Failed to execute sleek");
+
+    assert!(output.status.success());
+}
+
+#[test]
+fn test_extension_flag() {
+    let temp_dir = TempDir::new().unwrap();
+    let root = temp_dir.path();
+
+    fs::write(root.join("query.sql"), "select 1").unwrap();
+    fs::write(root.join("query.pgsql"), "select 2").unwrap();
+
+    let output = sleek_command()
+        .args(["--extension", "pgsql"])
+        .arg(root.to_str().unwrap())
+        .output()
+        .expect("Failed to execute sleek");
+
+    assert!(output.status.success());
+    assert_eq!(
+        fs::read_to_string(root.join("query.sql")).unwrap(),
+        "select 1"
+    );
+    assert!(
+        fs::read_to_string(root.join("query.pgsql"))
+            .unwrap()
+            .contains("SELECT")
+    );
+}
+
+#[test]
+fn test_sleek_off_on_directives() {
+    let input = "select a from t;\n-- sleek: off\nINSERT INTO t VALUES\n  (1,   2),\n  (10, 20);\n-- sleek: on\nselect b from u;\n";
+
+    let output = run_sleek_with_stdin(&[], input.as_bytes());
+
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert_eq!(
+        stdout,
+        "SELECT\n    a\nFROM\n    t;\n\n-- sleek: off\nINSERT INTO t VALUES\n  (1,   2),\n  (10, 20);\n-- sleek: on\n\nSELECT\n    b\nFROM\n    u;\n"
+    );
+
+    // The output is stable when checked again
+    let output = run_sleek_with_stdin(&["--check"], stdout.as_bytes());
+    assert!(output.status.success());
+}
+
+#[test]
+fn test_sleek_off_without_on_protects_rest_of_input() {
+    let input = "select a from t;\n-- sleek: off\nselect   b\n  from u;\n";
+
+    let output = run_sleek_with_stdin(&[], input.as_bytes());
+
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.ends_with("-- sleek: off\nselect   b\n  from u;\n"));
+}
+
+#[test]
+fn test_sleek_skip_directive_protects_next_statement() {
+    let input = "-- sleek: skip\nselect   x,\n  'a;b' as y from z;\nselect c from u;\n";
+
+    let output = run_sleek_with_stdin(&[], input.as_bytes());
+
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert_eq!(
+        stdout,
+        "-- sleek: skip\nselect   x,\n  'a;b' as y from z;\n\nSELECT\n    c\nFROM\n    u;\n"
+    );
+}
+
+#[test]
+fn test_sleek_ignore_file_directive() {
+    let temp_dir = TempDir::new().unwrap();
+    let file_path = temp_dir.path().join("dump.sql");
+    let input = "-- Generated by pg_dump\n-- sleek: ignore-file\nselect * from users";
+    fs::write(&file_path, input).unwrap();
+
+    let output = sleek_command()
+        .arg("--check")
+        .arg(file_path.to_str().unwrap())
+        .output()
+        .expect("Failed to execute sleek");
+    assert!(output.status.success());
+
+    let output = sleek_command()
+        .arg(file_path.to_str().unwrap())
+        .output()
+        .expect("Failed to execute sleek");
+    assert!(output.status.success());
+    assert_eq!(fs::read_to_string(&file_path).unwrap(), input);
+}
+
+#[test]
+fn test_sleek_ignore_file_directive_only_in_header() {
+    let input = "select 1;\n-- sleek: ignore-file\nselect 2;\n";
+
+    let output = run_sleek_with_stdin(&[], input.as_bytes());
+
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(
+        stdout.starts_with("SELECT"),
+        "directive after SQL is ignored"
+    );
+}
+
+#[test]
+fn test_range_flag_formats_only_overlapping_statements() {
+    let input = "select   1;\nselect * from users;\nselect   2;\n";
+
+    let output = run_sleek_with_stdin(&["--range", "2:2"], input.as_bytes());
+
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert_eq!(
+        stdout, "select   1;\nSELECT\n    *\nFROM\n    users;\nselect   2;\n",
+        "statements outside the range should be byte-identical"
+    );
+
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(
+        stderr.contains("Replaced bytes 12..32 (lines 2:2) with bytes 12..40 (lines 2:5)"),
+        "should report the replaced span, got: {stderr}"
+    );
+}
+
+#[test]
+fn test_range_flag_spanning_multiple_statements() {
+    let input = "select   1;\nselect 2;\nselect 3;\nselect   4;\n";
+
+    let output = run_sleek_with_stdin(&["--range", "2:3"], input.as_bytes());
+
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert_eq!(
+        stdout,
+        "select   1;\nSELECT\n    2;\n\nSELECT\n    3;\nselect   4;\n"
+    );
+}
+
+#[test]
+fn test_range_flag_leaves_protected_regions_alone() {
+    let input = "-- sleek: off\nselect   1;\nselect   2;\n-- sleek: on\n";
+    let output = run_sleek_with_stdin(&["--range", "3:3"], input.as_bytes());
+
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), input);
+
+    // Statements on both sides of a skipped one are formatted around it
+    let input = "select   1;\n-- sleek: skip\nselect   2;\nselect   3;\n";
+    let output = run_sleek_with_stdin(&["--range", "1:4"], input.as_bytes());
+
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "SELECT\n    1;\n-- sleek: skip\nselect   2;\nSELECT\n    3;\n"
+    );
+}
+
+#[test]
+fn test_range_flag_keeps_crlf_line_endings() {
+    let input = b"select 1;\r\nselect   2;\r\n";
+    let output = run_sleek_with_stdin(&["--range", "2:2"], input);
+
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "select 1;\r\nSELECT\r\n    2;\r\n"
+    );
+}
+
+#[test]
+fn test_byte_range_flag() {
+    let input = "select   1;\nselect * from users;\nselect   2;\n";
+
+    let output = run_sleek_with_stdin(&["--byte-range", "0:3"], input.as_bytes());
+
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert_eq!(
+        stdout,
+        "SELECT\n    1;\nselect * from users;\nselect   2;\n"
+    );
+}
+
+#[test]
+fn test_range_flag_with_check() {
+    let input = "SELECT\n    1;\nselect   2;\n";
+
+    let output = run_sleek_with_stdin(&["--check", "--range", "1:2"], input.as_bytes());
+    assert!(
+        output.status.success(),
+        "formatted statements in range should pass"
+    );
+
+    let output = run_sleek_with_stdin(&["--check", "--range", "3:3"], input.as_bytes());
+    assert!(!output.status.success());
+}
+
+#[test]
+fn test_invalid_range_values() {
+    for range in ["0:2", "3:1", "abc", "1-2"] {
+        let output = run_sleek_with_stdin(&["--range", range], b"select 1");
+        assert!(!output.status.success(), "--range {range} should fail");
+    }
+}
+
+#[test]
+fn test_markdown_file_sql_blocks_are_formatted() {
+    let temp_dir = TempDir::new().unwrap();
+    let file_path = temp_dir.path().join("README.md");
+    fs::write(
+        &file_path,
+        "# Queries\n\nSome prose.\n\n```sql\nselect * from users\n```\n\n```python\nprint( 'hi' )\n```\n",
+    )
+    .unwrap();
+
+    let output = sleek_command()
+        .arg(file_path.to_str().unwrap())
+        .output()
+        .expect("Failed to execute sleek");
+
+    assert!(output.status.success());
+    assert_eq!(
+        fs::read_to_string(&file_path).unwrap(),
+        "# Queries\n\nSome prose.\n\n```sql\nSELECT\n    *\nFROM\n    users\n```\n\n```python\nprint( 'hi' )\n```\n"
+    );
+}
+
+#[test]
+fn test_markdown_blocks_in_lists_keep_indentation() {
+    let input =
+        "1. Run:\n\n   ```postgresql title=\"query\"\n   select a,\n     b from t;\n   ```\n";
+
+    let output = run_sleek_with_stdin(&["--language", "markdown"], input.as_bytes());
+
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert_eq!(
+        stdout,
+        "1. Run:\n\n   ```postgresql title=\"query\"\n   SELECT\n       a,\n       b\n   FROM\n       t;\n   ```\n"
+    );
+
+    // The output is stable when checked again
+    let output = run_sleek_with_stdin(&["--language", "markdown", "--check"], stdout.as_bytes());
+    assert!(output.status.success());
+}
+
+#[test]
+fn test_markdown_check_reports_unformatted_blocks() {
+    let temp_dir = TempDir::new().unwrap();
+    let file_path = temp_dir.path().join("adr.md");
+    fs::write(
+        &file_path,
+        "```sql\nSELECT\n    1\n```\n\n```mysql\nselect 2\n```\n",
+    )
+    .unwrap();
+
+    let output = sleek_command()
+        .arg("--check")
+        .arg(file_path.to_str().unwrap())
+        .output()
+        .expect("Failed to execute sleek");
+
+    assert!(!output.status.success());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(
+        stderr.contains(&format!("{}:6 (SQL block)", file_path.display())),
+        "should report the unformatted block, got: {stderr}"
+    );
+    assert!(
+        !stderr.contains(":1 (SQL block)"),
+        "should not report formatted blocks"
+    );
+}
+
+#[test]
+fn test_language_flag_overrides_extension() {
+    let output = run_sleek_with_stdin(&["--language", "sql"], b"select 1");
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "SELECT\n    1\n");
+
+    let output = run_sleek_with_stdin(&["--language", "markdown"], b"select 1");
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "select 1",
+        "prose outside code blocks should be untouched"
+    );
+}
+
+#[test]
+fn test_dialect_flag() {
+    let input = b"select * from #recent where id = 1";
+
+    let output = run_sleek_with_stdin(&["--dialect", "tsql"], input);
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "SELECT\n    *\nFROM\n    #recent\nWHERE\n    id = 1\n"
+    );
+
+    let output = run_sleek_with_stdin(&[], input);
+    assert!(output.status.success());
+    assert!(
+        String::from_utf8(output.stdout)
+            .unwrap()
+            .contains("#recent where id = 1"),
+        "without a dialect, # starts a comment"
+    );
+}
+
+#[test]
+fn test_dialect_from_config_file() {
+    let temp_dir = TempDir::new().unwrap();
+    fs::write(temp_dir.path().join("sleek.toml"), "dialect = \"mysql\"\n").unwrap();
+
+    // A backslash-escaped quote must not end the string, or the `;` would
+    // split the statement in two
+    let input = b"-- sleek: skip\nselect 'it\\'s;'   ;\nselect   1;\n";
+    let output = run_sleek_with_stdin_in(temp_dir.path(), &[], input);
+
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "-- sleek: skip\nselect 'it\\'s;'   ;\n\nSELECT\n    1;\n"
+    );
+}
+
+#[test]
+fn test_dollar_quoted_function_bodies() {
+    let input = b"create function one() returns int as $$ select 1; $$ language sql;\n\
+create function two() returns int language plpgsql as $fn$ begin return 2; end; $fn$;\n\
+create function three() returns int as $$\n  return   3\n$$ language plpython3u;\n";
+
+    let output = run_sleek_with_stdin(&[], input);
+
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "CREATE FUNCTION one() RETURNS int AS $$\n    SELECT\n        1;\n$$ language SQL;\n\n\
+CREATE FUNCTION two() RETURNS int language plpgsql AS $fn$\n    BEGIN\n        RETURN 2;\n    END;\n$fn$;\n\n\
+CREATE FUNCTION three() RETURNS int AS $$\n  return   3\n$$ language plpython3u;\n"
+    );
+}
+
+#[test]
+fn test_go_batch_separator_is_kept() {
+    let output = run_sleek_with_stdin(
+        &["--dialect", "tsql"],
+        b"select   1\nGO\nselect   2\ngo 3\n",
+    );
+
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "SELECT\n    1\n\nGO\n\nSELECT\n    2\n\ngo 3\n"
+    );
+}
+
+#[test]
+fn test_delimiter_blocks_are_split_on_their_delimiter() {
+    let input = b"DELIMITER //\nselect 'a//b'; select 2 //\nDELIMITER ;\nselect   3;\n";
+    let output = run_sleek_with_stdin(&["--lines-between-queries", "1"], input);
+
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "DELIMITER //\nSELECT\n    'a//b';\nSELECT\n    2 //\nDELIMITER ;\nSELECT\n    3;\n"
+    );
+}
+
+#[test]
+fn test_psql_meta_commands_are_kept() {
+    let input = b"\\set ON_ERROR_STOP on\n\\i setup.sql\nselect * from users where id = :id;\n\\copy users to 'users.csv' csv header\n";
+    let output = run_sleek_with_stdin(&[], input);
+
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "\\set ON_ERROR_STOP on\n\\i setup.sql\n\nSELECT\n    *\nFROM\n    users\nWHERE\n    id = :id;\n\n\\copy users to 'users.csv' csv header\n"
+    );
+}
+
+#[test]
+fn test_client_commands_are_only_read_between_statements_or_in_their_dialect() {
+    let input = b"select\n  id,\n  go\nfrom t;\n";
+    let output = run_sleek_with_stdin(&["--dialect", "postgres"], input);
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "SELECT\n    id,\n    go\nFROM\n    t;\n"
+    );
+
+    // Without a dialect, a `go` column is left in its query
+    let output = run_sleek_with_stdin(&[], input);
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(!stdout.contains("\n\n"), "query was split: {stdout:?}");
+
+    // Between statements, client lines are still read without a dialect
+    let output = run_sleek_with_stdin(&[], b"select   1;\nGO\nselect   2;\n");
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "SELECT\n    1;\n\nGO\n\nSELECT\n    2;\n"
+    );
+
+    // Other dialects' client lines are formatted as SQL
+    let output = run_sleek_with_stdin(&["--dialect", "tsql"], b"DELIMITER ;\nselect   1;\n");
+    assert!(
+        !String::from_utf8(output.stdout)
+            .unwrap()
+            .starts_with("DELIMITER ;\n"),
+        "DELIMITER should not be read as a T-SQL client line"
+    );
+}
+
+#[test]
+fn test_formatted_files_are_not_rewritten() {
+    let temp_dir = TempDir::new().unwrap();
+    let file_path = temp_dir.path().join("formatted.sql");
+    fs::write(&file_path, "SELECT\n    1\n").unwrap();
+
+    let old_mtime = std::time::SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(1_000_000);
+    fs::File::options()
+        .write(true)
+        .open(&file_path)
+        .unwrap()
+        .set_modified(old_mtime)
+        .unwrap();
+
+    let output = sleek_command()
+        .arg(file_path.to_str().unwrap())
+        .output()
+        .expect("Failed to execute sleek");
+
+    assert!(output.status.success());
+    assert_eq!(
+        fs::metadata(&file_path).unwrap().modified().unwrap(),
+        old_mtime,
+        "an already formatted file should not be written"
+    );
+}
+
+#[test]
+fn test_write_leaves_no_temporary_files() {
+    let temp_dir = TempDir::new().unwrap();
+    let file_path = temp_dir.path().join("query.sql");
+    fs::write(&file_path, "select 1").unwrap();
+
+    let output = sleek_command()
+        .arg(file_path.to_str().unwrap())
+        .output()
+        .expect("Failed to execute sleek");
+
+    assert!(output.status.success());
+    assert_eq!(fs::read_to_string(&file_path).unwrap(), "SELECT\n    1\n");
+    let entries: Vec<_> = fs::read_dir(temp_dir.path())
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name())
+        .collect();
+    assert_eq!(entries, ["query.sql"]);
+}
+
+#[cfg(unix)]
+#[test]
+fn test_write_preserves_permissions() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let temp_dir = TempDir::new().unwrap();
+    let file_path = temp_dir.path().join("query.sql");
+    fs::write(&file_path, "select 1").unwrap();
+    fs::set_permissions(&file_path, fs::Permissions::from_mode(0o640)).unwrap();
+
+    let output = sleek_command()
+        .arg(file_path.to_str().unwrap())
+        .output()
+        .expect("Failed to execute sleek");
+
+    assert!(output.status.success());
+    assert_eq!(fs::read_to_string(&file_path).unwrap(), "SELECT\n    1\n");
+    let mode = fs::metadata(&file_path).unwrap().permissions().mode();
+    assert_eq!(mode & 0o777, 0o640);
+}
+
+#[cfg(unix)]
+#[test]
+fn test_write_through_symlink_keeps_the_link() {
+    let temp_dir = TempDir::new().unwrap();
+    let target = temp_dir.path().join("target.sql");
+    let link = temp_dir.path().join("link.sql");
+    fs::write(&target, "select 1").unwrap();
+    std::os::unix::fs::symlink(&target, &link).unwrap();
+
+    let output = sleek_command()
+        .arg(link.to_str().unwrap())
+        .output()
+        .expect("Failed to execute sleek");
+
+    assert!(output.status.success());
+    assert!(
+        fs::symlink_metadata(&link)
+            .unwrap()
+            .file_type()
+            .is_symlink()
+    );
+    assert_eq!(fs::read_link(&link).unwrap(), target);
+    assert_eq!(fs::read_to_string(&target).unwrap(), "SELECT\n    1\n");
+}
+
+#[test]
+fn test_crlf_line_endings_are_preserved() {
+    let temp_dir = TempDir::new().unwrap();
+    let file_path = temp_dir.path().join("windows.sql");
+    fs::write(&file_path, "select a from t;\r\nselect 1;\r\n").unwrap();
+
+    let output = sleek_command()
+        .arg(file_path.to_str().unwrap())
+        .output()
+        .expect("Failed to execute sleek");
+
+    assert!(output.status.success());
+    assert_eq!(
+        fs::read_to_string(&file_path).unwrap(),
+        "SELECT\r\n    a\r\nFROM\r\n    t;\r\n\r\nSELECT\r\n    1;\r\n"
+    );
+}
+
+#[test]
+fn test_end_of_line_flag() {
+    let output = run_sleek_with_stdin(&["--end-of-line", "lf"], b"select 1;\r\nselect 2;\r\n");
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "SELECT\n    1;\n\nSELECT\n    2;\n"
+    );
+
+    let output = run_sleek_with_stdin(&["--end-of-line", "crlf"], b"select 1");
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "SELECT\r\n    1\r\n"
+    );
+}
+
+#[test]
+fn test_utf8_byte_order_mark_is_preserved() {
+    let temp_dir = TempDir::new().unwrap();
+    let file_path = temp_dir.path().join("bom.sql");
+    fs::write(&file_path, b"\xEF\xBB\xBFselect 1").unwrap();
+
+    let output = sleek_command()
+        .arg(file_path.to_str().unwrap())
+        .output()
+        .expect("Failed to execute sleek");
+
+    assert!(output.status.success());
+    assert_eq!(
+        fs::read(&file_path).unwrap(),
+        b"\xEF\xBB\xBFSELECT\n    1\n"
+    );
+}
+
+#[test]
+fn test_utf16_files_are_written_back_as_utf16() {
+    let temp_dir = TempDir::new().unwrap();
+    let file_path = temp_dir.path().join("utf16.sql");
+    let utf16le = |text: &str| {
+        let mut bytes = vec![0xFF, 0xFE];
+        bytes.extend(text.encode_utf16().flat_map(u16::to_le_bytes));
+        bytes
+    };
+    fs::write(&file_path, utf16le("select 'é' from t")).unwrap();
+
+    let output = sleek_command()
+        .arg(file_path.to_str().unwrap())
+        .output()
+        .expect("Failed to execute sleek");
+
+    assert!(output.status.success());
+    assert_eq!(
+        fs::read(&file_path).unwrap(),
+        utf16le("SELECT\n    'é'\nFROM\n    t\n")
+    );
+}
+
+#[test]
+fn test_encoding_flag() {
+    let temp_dir = TempDir::new().unwrap();
+    let file_path = temp_dir.path().join("latin1.sql");
+    fs::write(&file_path, b"select 'caf\xE9'").unwrap();
+
+    let output = sleek_command()
+        .args(["--encoding", "latin1", file_path.to_str().unwrap()])
+        .output()
+        .expect("Failed to execute sleek");
+
+    assert!(output.status.success());
+    assert_eq!(fs::read(&file_path).unwrap(), b"SELECT\n    'caf\xE9'\n");
+}
+
+#[test]
+fn test_undecodable_file_error_names_the_file() {
+    let temp_dir = TempDir::new().unwrap();
+    let file_path = temp_dir.path().join("latin1.sql");
+    fs::write(&file_path, b"select 'caf\xE9'").unwrap();
+
+    let output = sleek_command()
+        .arg(file_path.to_str().unwrap())
+        .output()
+        .expect("Failed to execute sleek");
+
+    assert!(!output.status.success());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains(&format!(
+        "Failed to decode {} as utf-8",
+        file_path.display()
+    )));
+    assert!(stderr.contains("--encoding"));
+    assert_eq!(fs::read(&file_path).unwrap(), b"select 'caf\xE9'");
+}
+
+#[test]
+fn test_utf16_without_byte_order_mark_needs_encoding_flag() {
+    let temp_dir = TempDir::new().unwrap();
+    let file_path = temp_dir.path().join("utf16.sql");
+    let utf16: Vec<u8> = "select 1"
+        .encode_utf16()
+        .flat_map(u16::to_le_bytes)
+        .collect();
+    fs::write(&file_path, &utf16).unwrap();
+
+    // NUL bytes are valid UTF-8, but not something to format as SQL
+    let output = sleek_command()
+        .arg(file_path.to_str().unwrap())
+        .output()
+        .expect("Failed to execute sleek");
+
+    assert!(!output.status.success());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains(&format!(
+        "Failed to decode {} as utf-8",
+        file_path.display()
+    )));
+    assert!(stderr.contains("--encoding"));
+    assert_eq!(fs::read(&file_path).unwrap(), utf16);
+
+    let output = sleek_command()
+        .args(["--encoding", "utf-16le", file_path.to_str().unwrap()])
+        .output()
+        .expect("Failed to execute sleek");
+
+    assert!(output.status.success());
+    let expected: Vec<u8> = "SELECT\n    1\n"
+        .encode_utf16()
+        .flat_map(u16::to_le_bytes)
+        .collect();
+    assert_eq!(fs::read(&file_path).unwrap(), expected);
+}
+
+#[test]
+fn test_verify_refuses_formatting_that_changes_tokens() {
+    let temp_dir = TempDir::new().unwrap();
+    let file_path = temp_dir.path().join("hex.sql");
+    // sqlformat splits the hex literal into `0 x1F`
+    fs::write(&file_path, "select 1;\nselect 0x1F;\n").unwrap();
+
+    let output = sleek_command()
+        .arg(file_path.to_str().unwrap())
+        .output()
+        .expect("Failed to execute sleek");
+
+    assert!(!output.status.success());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains(&format!("Refusing to format {}", file_path.display())));
+    assert!(stderr.contains("line 2, column 8: expected `0x1F`, found `0`"));
+    assert_eq!(
+        fs::read_to_string(&file_path).unwrap(),
+        "select 1;\nselect 0x1F;\n"
+    );
+}
+
+#[test]
+fn test_check_reports_every_file_after_one_fails() {
+    let temp_dir = TempDir::new().unwrap();
+    let hex_path = temp_dir.path().join("a.sql");
+    let unformatted_path = temp_dir.path().join("b.sql");
+    fs::write(&hex_path, "select 0x1F;\n").unwrap();
+    fs::write(&unformatted_path, "select   1;\n").unwrap();
+
+    let output = sleek_command()
+        .arg("--check")
+        .arg(&hex_path)
+        .arg(&unformatted_path)
+        .output()
+        .expect("Failed to execute sleek");
+
+    assert!(!output.status.success());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    let refused = stderr
+        .find(&format!("Refusing to format {}", hex_path.display()))
+        .unwrap();
+    let unformatted = stderr
+        .find(&format!("Would reformat: {}", unformatted_path.display()))
+        .unwrap();
+    assert!(refused < unformatted);
+    // Both counts are reported
+    assert!(stderr.ends_with(
+        "1 file is not formatted correctly. Run sleek without --check, --diff or --list-different to format it.\n1 file could not be formatted\n"
+    ));
+}
+
+#[cfg(unix)]
+#[test]
+fn test_unreadable_file_is_reported_by_name() {
+    let temp_dir = TempDir::new().unwrap();
+    let file_path = temp_dir.path().join("x.sql");
+    std::os::unix::fs::symlink("missing.sql", &file_path).unwrap();
+
+    let output = sleek_command()
+        .args(["--check", "--report", "json", "--report-file"])
+        .arg(temp_dir.path().join("report.json"))
+        .arg(&file_path)
+        .output()
+        .expect("Failed to execute sleek");
+
+    assert!(!output.status.success());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.starts_with(&format!("Failed to read {}: ", file_path.display())));
+
+    let report = fs::read_to_string(temp_dir.path().join("report.json")).unwrap();
+    let report: serde_json::Value = serde_json::from_str(&report).unwrap();
+    let error = &report["files"][0]["error"];
+    assert_eq!(error["kind"], "read");
+    assert!(error["message"].as_str().unwrap().contains("x.sql"));
+}
+
+#[test]
+fn test_no_verify_flag() {
+    let output = run_sleek_with_stdin(&["--no-verify"], b"select 0x1F");
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "SELECT\n    0 x1F\n"
+    );
+}
+
+#[test]
+fn test_ensure_idempotent_reaches_a_fixed_point() {
+    let input = b"select * from t where m between 1 and 10;";
+    let output = run_sleek_with_stdin(&["--ensure-idempotent"], input);
+    assert!(output.status.success());
+
+    let formatted = output.stdout;
+    let again = run_sleek_with_stdin(&["--check"], &formatted);
+    assert!(
+        again.status.success(),
+        "output should already be formatted:\n{}",
+        String::from_utf8_lossy(&formatted)
+    );
+}
+
+/// SQL samples from `tests/corpus` and the dialect golden files, with the
+/// dialect to read each one as.
+fn corpus() -> Vec<(std::path::PathBuf, Option<String>)> {
+    let mut samples = Vec::new();
+    for entry in fs::read_dir("tests/corpus").unwrap() {
+        samples.push((entry.unwrap().path(), None));
+    }
+    for dir in fs::read_dir("tests/golden").unwrap() {
+        let dir = dir.unwrap().path();
+        let dialect = dir.file_name().unwrap().to_string_lossy().to_string();
+        for entry in fs::read_dir(&dir).unwrap() {
+            let path = entry.unwrap().path();
+            if !path.to_string_lossy().ends_with(".expected.sql") {
+                samples.push((path, Some(dialect.clone())));
+            }
+        }
+    }
+    samples.sort();
+    samples
+}
+
+/// Input sqlformat does not format to a fixed point in one pass: a row
+/// written right after the previous row's comma is only indented once it
+/// starts a line of its own.
+const UNSTABLE_SAMPLE: &str = "insert into t (a, b) values (1, 2),(3, 4);\n";
+
+/// Settings the corpus is formatted with.
+const CORPUS_SETTINGS: [&[&str]; 3] = [&[], &["-U", "false"], &["-i", "2", "-l", "1"]];
+
+#[test]
+fn test_formatting_is_idempotent_over_corpus() {
+    for (path, dialect) in corpus() {
+        let input = fs::read(&path).unwrap();
+        for args in CORPUS_SETTINGS {
+            let mut args = args.to_vec();
+            if let Some(dialect) = &dialect {
+                args.extend(["--dialect", dialect]);
+            }
+
+            let output = run_sleek_with_stdin(&args, &input);
+            assert!(
+                output.status.success(),
+                "{} {args:?} failed: {}",
+                path.display(),
+                String::from_utf8_lossy(&output.stderr)
+            );
+
+            args.push("--check");
+            let again = run_sleek_with_stdin(&args, &output.stdout);
+            assert!(
+                again.status.success(),
+                "{} {args:?} is not idempotent:\n{}",
+                path.display(),
+                String::from_utf8_lossy(&output.stdout)
+            );
+        }
+    }
+}
+
+#[test]
+fn test_ensure_idempotent_settles_unstable_input() {
+    // A known instability, so --ensure-idempotent has something to settle
+    let output = run_sleek_with_stdin(&[], UNSTABLE_SAMPLE.as_bytes());
+    assert!(output.status.success());
+    let again = run_sleek_with_stdin(&["--check"], &output.stdout);
+    assert!(
+        !again.status.success(),
+        "the sample is stable now, so pick another one"
+    );
+
+    let mut samples = vec![(UNSTABLE_SAMPLE.as_bytes().to_vec(), None, "sample".into())];
+    for (path, dialect) in corpus() {
+        samples.push((
+            fs::read(&path).unwrap(),
+            dialect,
+            path.display().to_string(),
+        ));
+    }
+    for (input, dialect, name) in samples {
+        for args in CORPUS_SETTINGS {
+            let mut args = args.to_vec();
+            if let Some(dialect) = &dialect {
+                args.extend(["--dialect", dialect]);
+            }
+
+            let mut format_args = args.clone();
+            format_args.push("--ensure-idempotent");
+            let output = run_sleek_with_stdin(&format_args, &input);
+            assert!(
+                output.status.success(),
+                "{name} {args:?} failed: {}",
+                String::from_utf8_lossy(&output.stderr)
+            );
+
+            args.push("--check");
+            let again = run_sleek_with_stdin(&args, &output.stdout);
+            assert!(
+                again.status.success(),
+                "{name} {args:?} is not settled:\n{}",
+                String::from_utf8_lossy(&output.stdout)
+            );
+        }
+    }
+}
+
+#[test]
+fn test_parallel_output_is_sorted_and_deterministic() {
+    let temp_dir = TempDir::new().unwrap();
+    let mut expected = Vec::new();
+    for i in 0..40 {
+        let path = temp_dir.path().join(format!("query_{i:02}.sql"));
+        fs::write(&path, format!("select {i} from t")).unwrap();
+        expected.push(format!("Would reformat: {}", path.display()));
+    }
+    expected
+        .push("40 files are not formatted correctly. Run sleek without --check, --diff or --list-different to format them.".into());
+
+    for jobs in ["1", "4", "16"] {
+        let output = sleek_command()
+            .args(["--check", "--jobs", jobs, temp_dir.path().to_str().unwrap()])
+            .output()
+            .expect("Failed to execute sleek");
+
+        assert!(!output.status.success());
+        let stderr = String::from_utf8(output.stderr).unwrap();
+        assert_eq!(
+            stderr.lines().collect::<Vec<_>>(),
+            expected,
+            "--jobs {jobs}"
+        );
+    }
+}
+
+#[test]
+fn test_overlapping_patterns_format_each_file_once() {
+    let temp_dir = TempDir::new().unwrap();
+    let dir = temp_dir.path().to_str().unwrap();
+    fs::write(temp_dir.path().join("b.sql"), "select 1").unwrap();
+    fs::write(temp_dir.path().join("a.sql"), "select 2").unwrap();
+
+    let output = sleek_command()
+        .args([
+            "--list-different",
+            &format!("{dir}/b.sql"),
+            &format!("{dir}/*.sql"),
+            dir,
+        ])
+        .output()
+        .expect("Failed to execute sleek");
+
+    assert!(!output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        format!("{dir}/a.sql\n{dir}/b.sql\n")
+    );
+}
+
+#[test]
+fn test_jobs_flag_formats_every_file() {
+    let temp_dir = TempDir::new().unwrap();
+    for i in 0..20 {
+        fs::write(temp_dir.path().join(format!("{i}.sql")), "select 1").unwrap();
+    }
+
+    let output = sleek_command()
+        .args(["-j", "3", temp_dir.path().to_str().unwrap()])
+        .output()
+        .expect("Failed to execute sleek");
+
+    assert!(output.status.success());
+    for i in 0..20 {
+        let path = temp_dir.path().join(format!("{i}.sql"));
+        assert_eq!(fs::read_to_string(path).unwrap(), "SELECT\n    1\n");
+    }
+
+    let output = sleek_command()
+        .args(["--jobs", "0", temp_dir.path().to_str().unwrap()])
+        .output()
+        .expect("Failed to execute sleek");
+    assert!(!output.status.success());
+}
+
+#[test]
+fn test_cache_records_formatted_files() {
+    let temp_dir = TempDir::new().unwrap();
+    let cache_home = temp_dir.path().join("cache");
+    let sql_dir = temp_dir.path().join("sql");
+    fs::create_dir(&sql_dir).unwrap();
+    fs::write(sql_dir.join("formatted.sql"), "SELECT\n    1\n").unwrap();
+    fs::write(sql_dir.join("unformatted.sql"), "select 2").unwrap();
+    let cache_file = cache_home.join("sleek/formatted-v2");
+
+    let run = |args: &[&str]| {
+        sleek_command()
+            .env("XDG_CACHE_HOME", &cache_home)
+            .args(args)
+            .arg(sql_dir.to_str().unwrap())
+            .output()
+            .expect("Failed to execute sleek")
+    };
+
+    assert!(!run(&["--check", "--cache", "--no-cache"]).status.success());
+    assert!(!cache_file.exists(), "--no-cache should not write a cache");
+
+    assert!(!run(&["--check", "--cache"]).status.success());
+    assert_eq!(fs::read_to_string(&cache_file).unwrap().lines().count(), 1);
+
+    // Formatting records the written file once it is known to be stable
+    assert!(run(&["--cache"]).status.success());
+    assert_eq!(fs::read_to_string(&cache_file).unwrap().lines().count(), 1);
+    assert!(run(&["--check", "--cache"]).status.success());
+    assert_eq!(fs::read_to_string(&cache_file).unwrap().lines().count(), 2);
+    assert!(run(&["--cache", "--ensure-idempotent"]).status.success());
+    assert_eq!(fs::read_to_string(&cache_file).unwrap().lines().count(), 4);
+
+    // Cached results do not carry over to other settings
+    assert!(!run(&["--check", "--cache", "-U", "false"]).status.success());
+
+    let output = sleek_command()
+        .env("XDG_CACHE_HOME", &cache_home)
+        .args(["cache", "clear"])
+        .output()
+        .expect("Failed to execute sleek");
+    assert!(output.status.success());
+    assert!(!cache_file.exists());
+}
+
+#[test]
+fn test_cache_does_not_record_output_that_is_not_a_fixed_point() {
+    let temp_dir = TempDir::new().unwrap();
+    let cache_home = temp_dir.path().join("cache");
+    let file_path = temp_dir.path().join("a.sql");
+    // Formatting this once leaves output that formats differently again
+    fs::write(&file_path, UNSTABLE_SAMPLE).unwrap();
+
+    let run = |args: &[&str]| {
+        sleek_command()
+            .env("XDG_CACHE_HOME", &cache_home)
+            .args(args)
+            .arg(&file_path)
+            .output()
+            .expect("Failed to execute sleek")
+    };
+
+    assert!(run(&["--cache"]).status.success());
+    assert!(!run(&["--check", "--cache"]).status.success());
+    assert!(!run(&["--check"]).status.success());
+}
+
+#[test]
+fn test_watch_formats_changed_files() {
+    use std::io::{BufRead, BufReader};
+    use std::sync::mpsc;
+    use std::time::Duration;
+
+    let temp_dir = TempDir::new().unwrap();
+    let dir = temp_dir.path();
+    let mut child = sleek_command()
+        .args(["--watch", dir.to_str().unwrap()])
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("Failed to start sleek");
+
+    let (sender, lines) = mpsc::channel();
+    let stderr = child.stderr.take().unwrap();
+    std::thread::spawn(move || {
+        for line in BufReader::new(stderr).lines() {
+            if sender.send(line.unwrap()).is_err() {
+                break;
+            }
+        }
+    });
+    let next_line = || lines.recv_timeout(Duration::from_secs(10)).ok();
+
+    assert_eq!(
+        next_line().as_deref(),
+        Some("Watching for changes, press Ctrl-C to stop")
+    );
+
+    let query = dir.join("query.sql");
+    fs::write(&query, "select 1").unwrap();
+    assert_eq!(next_line(), Some(format!("Formatted {}", query.display())));
+    assert_eq!(fs::read_to_string(&query).unwrap(), "SELECT\n    1\n");
+
+    // An error in one file is logged and watching carries on
+    let invalid = dir.join("invalid.sql");
+    fs::write(&invalid, b"select '\xE9'").unwrap();
+    assert!(next_line().unwrap().contains("Failed to decode"));
+
+    fs::write(dir.join("notes.txt"), "select 2").unwrap();
+    let other = dir.join("other.sql");
+    fs::write(&other, "select 3").unwrap();
+    assert_eq!(next_line(), Some(format!("Formatted {}", other.display())));
+
+    // Its own writes do not trigger another format
+    assert_eq!(lines.recv_timeout(Duration::from_secs(1)).ok(), None);
+    assert_eq!(
+        fs::read_to_string(dir.join("notes.txt")).unwrap(),
+        "select 2"
+    );
+
+    child.kill().unwrap();
+    child.wait().unwrap();
+}
+
+#[test]
+fn test_json_report() {
+    let temp_dir = TempDir::new().unwrap();
+    let dir = temp_dir.path();
+    fs::write(dir.join("a.sql"), "select 1").unwrap();
+    fs::write(dir.join("b.sql"), "SELECT\n    1\n").unwrap();
+    fs::write(dir.join("c.sql"), b"select '\xE9'").unwrap();
+
+    let run = |args: &[&str]| {
+        let output = sleek_command()
+            .args(args)
+            .args(["--report", "json", dir.to_str().unwrap()])
+            .output()
+            .expect("Failed to execute sleek");
+        let report: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+        (output.status.success(), report)
+    };
+
+    let (success, report) = run(&["--check", "--diff"]);
+    assert!(!success);
+    assert_eq!(report["version"], 1);
+    let files = report["files"].as_array().unwrap();
+    assert_eq!(files.len(), 3);
+
+    assert_eq!(files[0]["path"], format!("{}/a.sql", dir.display()));
+    assert_eq!(files[0]["status"], "would-reformat");
+    assert_eq!(files[0]["bytes_before"], 8);
+    assert_eq!(files[0]["bytes_after"], 13);
+    assert_eq!(
+        files[0]["changed_lines"],
+        serde_json::json!([{ "start": 1, "end": 1 }])
+    );
+    assert!(files[0]["diff"].as_str().unwrap().contains("+SELECT"));
+
+    assert_eq!(files[1]["status"], "unchanged");
+    assert_eq!(files[1]["diff"], serde_json::Value::Null);
+
+    assert_eq!(files[2]["status"], "error");
+    assert_eq!(files[2]["error"]["kind"], "decode");
+    assert!(
+        files[2]["error"]["message"]
+            .as_str()
+            .unwrap()
+            .contains("c.sql")
+    );
+
+    // Without --diff, no diff is included, and formatting carries on past errors
+    let (success, report) = run(&[]);
+    assert!(!success);
+    assert_eq!(report["files"][0]["status"], "formatted");
+    assert_eq!(report["files"][0]["diff"], serde_json::Value::Null);
+    assert_eq!(report["files"][2]["status"], "error");
+    assert_eq!(
+        fs::read_to_string(dir.join("a.sql")).unwrap(),
+        "SELECT\n    1\n"
+    );
+
+    fs::remove_file(dir.join("c.sql")).unwrap();
+    let (success, report) = run(&["--check"]);
+    assert!(success);
+    assert_eq!(report["files"][0]["status"], "unchanged");
+}
+
+/// Creates a formatted file, a file unformatted on line 4 and an undecodable file.
+fn report_fixture() -> TempDir {
+    let temp_dir = TempDir::new().unwrap();
+    let dir = temp_dir.path();
+    fs::write(dir.join("a.sql"), "SELECT\n    1;\n\nselect   2;\n").unwrap();
+    fs::write(dir.join("b.sql"), "SELECT\n    1\n").unwrap();
+    fs::write(dir.join("c&d.sql"), b"select '\xE9'").unwrap();
+    temp_dir
+}
+
+#[test]
+fn test_sarif_report_file_keeps_console_output() {
+    let temp_dir = report_fixture();
+    let dir = temp_dir.path();
+    let report_path = dir.join("report.sarif");
+
+    let output = sleek_command()
+        .args(["--check", "--report", "sarif", "--report-file"])
+        .arg(&report_path)
+        .arg(dir.join("a.sql"))
+        .arg(dir.join("b.sql"))
+        .output()
+        .expect("Failed to execute sleek");
+
+    assert!(!output.status.success());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains(&format!("Would reformat: {}", dir.join("a.sql").display())));
+
+    let sarif: serde_json::Value =
+        serde_json::from_str(&fs::read_to_string(&report_path).unwrap()).unwrap();
+    assert_eq!(sarif["version"], "2.1.0");
+    let results = sarif["runs"][0]["results"].as_array().unwrap();
+    assert_eq!(results.len(), 1);
+    assert_eq!(results[0]["ruleId"], "unformatted");
+    let location = &results[0]["locations"][0]["physicalLocation"];
+    assert!(
+        location["artifactLocation"]["uri"]
+            .as_str()
+            .unwrap()
+            .ends_with("/a.sql")
+    );
+    assert_eq!(location["region"]["startLine"], 4);
+    assert_eq!(location["region"]["endLine"], 4);
+}
+
+#[test]
+fn test_checkstyle_report() {
+    let temp_dir = report_fixture();
+    let dir = temp_dir.path();
+
+    let output = sleek_command()
+        .args(["--check", "--report", "checkstyle"])
+        .arg(dir)
+        .output()
+        .expect("Failed to execute sleek");
+
+    assert!(!output.status.success());
+    let xml = String::from_utf8(output.stdout).unwrap();
+    assert!(xml.starts_with("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<checkstyle"));
+    assert!(xml.contains(&format!("<file name=\"{}/a.sql\">", dir.display())));
+    assert!(xml.contains(
+        "<error line=\"4\" severity=\"warning\" message=\"Line 4 is not formatted\" source=\"sleek.unformatted\"/>"
+    ));
+    assert!(xml.contains(&format!("<file name=\"{}/c&amp;d.sql\">", dir.display())));
+    assert!(xml.contains("severity=\"error\""));
+    assert!(xml.trim_end().ends_with("</checkstyle>"));
+}
+
+#[test]
+fn test_junit_report() {
+    let temp_dir = report_fixture();
+    let dir = temp_dir.path();
+
+    let output = sleek_command()
+        .args(["--check", "--report", "junit"])
+        .arg(dir)
+        .output()
+        .expect("Failed to execute sleek");
+
+    assert!(!output.status.success());
+    let xml = String::from_utf8(output.stdout).unwrap();
+    assert!(xml.contains("<testsuite name=\"sleek\" tests=\"3\" failures=\"1\" errors=\"1\">"));
+    assert!(
+        xml.contains(
+            "<failure message=\"File is not formatted\">Line 4 is not formatted</failure>"
+        )
+    );
+    assert!(xml.contains(&format!(
+        "<testcase name=\"{}/b.sql\" classname=\"sleek\"/>",
+        dir.display()
+    )));
+    assert!(xml.contains("<error message=\"Failed to decode"));
+}
+
+#[test]
+fn test_github_report() {
+    let temp_dir = report_fixture();
+    let dir = temp_dir.path();
+
+    let output = sleek_command()
+        .current_dir(dir)
+        .args(["--check", "--report", "github", "a.sql", "b.sql", "c&d.sql"])
+        .output()
+        .expect("Failed to execute sleek");
+
+    assert!(!output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert_eq!(
+        stdout.lines().collect::<Vec<_>>(),
+        [
+            "::error file=a.sql,line=4,endLine=4::Line 4 is not formatted",
+            "::error file=c&d.sql::Failed to decode c&d.sql as utf-8. Use --encoding to read it with another encoding.",
+        ]
+    );
+    // The usual output is kept
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("Would reformat: a.sql"));
+}
+
+#[test]
+fn test_github_report_in_github_actions() {
+    let temp_dir = report_fixture();
+    let dir = temp_dir.path();
+
+    let output = sleek_command()
+        .current_dir(dir)
+        .env("GITHUB_ACTIONS", "true")
+        .args(["--check", "a.sql"])
+        .output()
+        .expect("Failed to execute sleek");
+
+    assert!(!output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "::error file=a.sql,line=4,endLine=4::Line 4 is not formatted\n"
+    );
+
+    // Output meant for other tools is left alone, colors included
+    let output = sleek_command()
+        .current_dir(dir)
+        .env("GITHUB_ACTIONS", "true")
+        .args(["--list-different", "a.sql"])
+        .output()
+        .expect("Failed to execute sleek");
+    assert!(!output.status.success());
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "a.sql\n");
+
+    let output = sleek_command()
+        .current_dir(dir)
+        .env("GITHUB_ACTIONS", "true")
+        .args(["--diff", "--color", "always", "a.sql"])
+        .output()
+        .expect("Failed to execute sleek");
+    assert!(!output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(!stdout.contains("::error"));
+    assert!(stdout.contains("\x1b["));
+
+    // Formatting stdin is unaffected
+    let output = sleek_command()
+        .env("GITHUB_ACTIONS", "true")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .and_then(|mut child| {
+            child.stdin.take().unwrap().write_all(b"select 1")?;
+            child.wait_with_output()
+        })
+        .expect("Failed to execute sleek");
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "SELECT\n    1\n");
+}
+
+/// Runs git in `dir`, failing the test if it fails.
+fn git(dir: &Path, args: &[&str]) {
+    let status = Command::new("git")
+        .current_dir(dir)
+        .args([
+            "-c",
+            "user.name=sleek",
+            "-c",
+            "user.email=sleek@example.com",
+        ])
+        .args(args)
+        .stdout(Stdio::null())
+        .status()
+        .expect("Failed to run git");
+    assert!(status.success(), "git {args:?} failed");
+}
+
+/// Creates a repository with committed, modified, untracked and staged files,
+/// none of them formatted.
+fn git_fixture() -> TempDir {
+    let temp_dir = TempDir::new().unwrap();
+    let dir = temp_dir.path();
+    git(dir, &["init", "-q"]);
+    fs::create_dir(dir.join("sub")).unwrap();
+    for name in ["committed.sql", "modified.sql", "sub/deleted.sql"] {
+        fs::write(dir.join(name), "select 1").unwrap();
+    }
+    git(dir, &["add", "."]);
+    git(dir, &["commit", "-q", "-m", "Initial commit"]);
+
+    fs::write(dir.join("modified.sql"), "select 2").unwrap();
+    fs::remove_file(dir.join("sub/deleted.sql")).unwrap();
+    fs::write(dir.join("untracked.sql"), "select 3").unwrap();
+    fs::write(dir.join("sub/staged.sql"), "select 4").unwrap();
+    git(dir, &["add", "sub/staged.sql"]);
+    temp_dir
+}
+
+#[test]
+fn test_changed_since() {
+    let temp_dir = git_fixture();
+    let dir = temp_dir.path();
+
+    let output = sleek_command()
+        .current_dir(dir.join("sub"))
+        .args(["--list-different", "--changed-since", "HEAD", ".."])
+        .output()
+        .expect("Failed to execute sleek");
+
+    assert!(!output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "../modified.sql\n../sub/staged.sql\n../untracked.sql\n"
+    );
+
+    // Only files matching the arguments are formatted
+    let output = sleek_command()
+        .current_dir(dir)
+        .args(["--changed-since", "HEAD", "sub"])
+        .output()
+        .expect("Failed to execute sleek");
+
+    assert!(output.status.success());
+    assert_eq!(
+        fs::read_to_string(dir.join("sub/staged.sql")).unwrap(),
+        "SELECT\n    4\n"
+    );
+    assert_eq!(
+        fs::read_to_string(dir.join("modified.sql")).unwrap(),
+        "select 2"
+    );
+    assert_eq!(
+        fs::read_to_string(dir.join("committed.sql")).unwrap(),
+        "select 1"
+    );
+}
+
+#[test]
+fn test_staged() {
+    let temp_dir = git_fixture();
+    let dir = temp_dir.path();
+
+    let output = sleek_command()
+        .current_dir(dir)
+        .args(["--list-different", "--staged", "."])
+        .output()
+        .expect("Failed to execute sleek");
+
+    assert!(!output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "./sub/staged.sql\n"
+    );
+
+    // Checks read the staged contents, so formatting the working tree alone
+    // does not pass
+    fs::write(dir.join("sub/staged.sql"), "SELECT\n    4\n").unwrap();
+    let output = sleek_command()
+        .current_dir(dir.join("sub"))
+        .args(["--list-different", "--staged", "."])
+        .output()
+        .expect("Failed to execute sleek");
+
+    assert!(!output.status.success());
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "./staged.sql\n");
+}
+
+#[test]
+fn test_changed_since_unknown_revision() {
+    let temp_dir = git_fixture();
+    let dir = temp_dir.path();
+
+    let output = sleek_command()
+        .current_dir(dir)
+        .args(["--check", "--changed-since", "no-such-branch", "."])
+        .output()
+        .expect("Failed to execute sleek");
+
+    assert!(!output.status.success());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("Failed to run git diff"));
+    assert!(stderr.contains("no-such-branch"));
+}
+
+#[test]
+fn test_lines_changed_since() {
+    let temp_dir = TempDir::new().unwrap();
+    let dir = temp_dir.path();
+    git(dir, &["init", "-q"]);
+    fs::write(
+        dir.join("legacy.sql"),
+        "select  a from t;\n\nselect b from u;\n\n  select c from v;\n",
+    )
+    .unwrap();
+    fs::write(dir.join("untouched.sql"), "select  d from w;\n").unwrap();
+    git(dir, &["add", "."]);
+    git(dir, &["commit", "-q", "-m", "Initial commit"]);
+
+    fs::write(
+        dir.join("legacy.sql"),
+        "select  a from t;\n\nselect b, b2 from u;\n\n  select c from v;\n  select e from x;\n",
+    )
+    .unwrap();
+    fs::write(dir.join("new.sql"), "select f from y;\n").unwrap();
+
+    let output = sleek_command()
+        .current_dir(dir)
+        .args(["--lines-changed-since", "HEAD", "."])
+        .output()
+        .expect("Failed to execute sleek");
+
+    assert!(output.status.success());
+    // Only the changed statements are formatted, keeping their indentation
+    assert_eq!(
+        fs::read_to_string(dir.join("legacy.sql")).unwrap(),
+        "select  a from t;\n\nSELECT\n    b,\n    b2\nFROM\n    u;\n\n  select c from v;\n  SELECT\n      e\n  FROM\n      x;\n"
+    );
+    // Untracked files are new, so every line is formatted
+    assert_eq!(
+        fs::read_to_string(dir.join("new.sql")).unwrap(),
+        "SELECT\n    f\nFROM\n    y;\n"
+    );
+    assert_eq!(
+        fs::read_to_string(dir.join("untouched.sql")).unwrap(),
+        "select  d from w;\n"
+    );
+
+    let output = sleek_command()
+        .current_dir(dir)
+        .args(["--check", "--lines-changed-since", "HEAD", "."])
+        .output()
+        .expect("Failed to execute sleek");
+    assert!(output.status.success());
+}
+
+#[test]
+fn test_lines_changed_since_reads_quoted_paths_from_one_diff() {
+    let temp_dir = TempDir::new().unwrap();
+    let dir = temp_dir.path();
+    git(dir, &["init", "-q"]);
+    fs::create_dir(dir.join("sub")).unwrap();
+    let names = ["sub/plain.sql", "sub/caf\u{e9} \"q\".sql"];
+    for name in names {
+        fs::write(dir.join(name), "select  1;\n\nselect  2;\n").unwrap();
+    }
+    git(dir, &["add", "."]);
+    git(dir, &["commit", "-q", "-m", "Initial commit"]);
+
+    // The diff adds a line `+++ 4;`, like a `+++ b/<path>` header
+    for name in names {
+        fs::write(dir.join(name), "select  1;\n\nselect 3\n++ 4;\n").unwrap();
+    }
+
+    let output = sleek_command()
+        .current_dir(dir.join("sub"))
+        .args(["--lines-changed-since", "HEAD", "."])
+        .output()
+        .expect("Failed to execute sleek");
+
+    assert!(output.status.success());
+    for name in names {
+        assert_eq!(
+            fs::read_to_string(dir.join(name)).unwrap(),
+            "select  1;\n\nSELECT\n    3 + + 4;\n",
+            "only the changed statement of {name} should be formatted"
+        );
+    }
+}
+
+#[test]
+fn test_lines_changed_since_leaves_protected_regions_alone() {
+    let temp_dir = TempDir::new().unwrap();
+    let dir = temp_dir.path();
+    git(dir, &["init", "-q"]);
+    fs::write(
+        dir.join("a.sql"),
+        "-- sleek: off\nselect   1;\nselect   2;\n-- sleek: on\nselect 3;\n",
+    )
+    .unwrap();
+    git(dir, &["add", "."]);
+    git(dir, &["commit", "-q", "-m", "Initial commit"]);
+
+    let edited = "-- sleek: off\nselect   1;\nselect   22;\n-- sleek: on\nselect 33;\n";
+    fs::write(dir.join("a.sql"), edited).unwrap();
+
+    let output = sleek_command()
+        .current_dir(dir)
+        .args(["--lines-changed-since", "HEAD", "a.sql"])
+        .output()
+        .expect("Failed to execute sleek");
+
+    assert!(output.status.success());
+    assert_eq!(
+        fs::read_to_string(dir.join("a.sql")).unwrap(),
+        "-- sleek: off\nselect   1;\nselect   22;\n-- sleek: on\nSELECT\n    33;\n"
+    );
+}
+
+#[test]
+fn test_install_hook() {
+    let temp_dir = TempDir::new().unwrap();
+    let dir = temp_dir.path();
+    git(dir, &["init", "-q"]);
+    let hook = dir.join(".git/hooks/pre-commit");
+
+    let output = sleek_command()
+        .current_dir(dir)
+        .arg("install-hook")
+        .output()
+        .expect("Failed to execute sleek");
+    assert!(output.status.success());
+    let script = fs::read_to_string(&hook).unwrap();
+    assert!(script.starts_with("#!/bin/sh\n"));
+    assert!(script.contains("sleek --check --staged ."));
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let mode = fs::metadata(&hook).unwrap().permissions().mode();
+        assert_eq!(mode & 0o111, 0o111, "hook should be executable");
+    }
+
+    // An existing hook is kept unless --force is given
+    fs::write(&hook, "#!/bin/sh\necho custom\n").unwrap();
+    let output = sleek_command()
+        .current_dir(dir)
+        .args(["install-hook", "--fix"])
+        .output()
+        .expect("Failed to execute sleek");
+    assert!(!output.status.success());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("already exists"));
+    assert!(stderr.contains("--force"));
+    assert_eq!(
+        fs::read_to_string(&hook).unwrap(),
+        "#!/bin/sh\necho custom\n"
+    );
+
+    let output = sleek_command()
+        .current_dir(dir)
+        .args(["install-hook", "--fix", "--force"])
+        .output()
+        .expect("Failed to execute sleek");
+    assert!(output.status.success());
+    let script = fs::read_to_string(&hook).unwrap();
+    assert!(script.contains("sleek --staged ."));
+    assert!(script.contains("git add"));
+}
+
+#[cfg(unix)]
+#[test]
+fn test_installed_hooks_run_on_commit() {
+    let temp_dir = TempDir::new().unwrap();
+    let dir = temp_dir.path();
+    git(dir, &["init", "-q"]);
+    fs::write(dir.join("query.sql"), "select 1\n").unwrap();
+    fs::write(dir.join("notes.txt"), "select 1\n").unwrap();
+    git(dir, &["add", "."]);
+
+    // The hooks run the sleek found on PATH
+    let bin_dir = Path::new(env!("CARGO_BIN_EXE_sleek")).parent().unwrap();
+    let path = format!("{}:{}", bin_dir.display(), std::env::var("PATH").unwrap());
+    let commit = || {
+        Command::new("git")
+            .current_dir(dir)
+            .env("PATH", &path)
+            .env_remove("GITHUB_ACTIONS")
+            .args([
+                "-c",
+                "user.name=sleek",
+                "-c",
+                "user.email=sleek@example.com",
+            ])
+            .args(["commit", "-q", "-m", "Add query"])
+            .output()
+            .expect("Failed to run git")
+    };
+
+    sleek_command()
+        .current_dir(dir)
+        .arg("install-hook")
+        .output()
+        .expect("Failed to execute sleek");
+    let output = commit();
+    assert!(
+        !output.status.success(),
+        "unformatted SQL should be refused"
+    );
+    assert!(
+        String::from_utf8(output.stderr)
+            .unwrap()
+            .contains("Would reformat: ./query.sql")
+    );
+
+    // The staged contents are checked, not the working tree
+    fs::write(dir.join("query.sql"), "SELECT\n    1\n").unwrap();
+    assert!(!commit().status.success());
+
+    // Formatting a file with unstaged changes would commit them too
+    sleek_command()
+        .current_dir(dir)
+        .args(["install-hook", "--fix", "--force"])
+        .output()
+        .expect("Failed to execute sleek");
+    fs::write(dir.join("query.sql"), "select 1\nselect 2\n").unwrap();
+    let output = commit();
+    assert!(!output.status.success());
+    assert!(
+        String::from_utf8(output.stderr)
+            .unwrap()
+            .contains("unstaged changes")
+    );
+    assert_eq!(
+        fs::read_to_string(dir.join("query.sql")).unwrap(),
+        "select 1\nselect 2\n"
+    );
+
+    fs::write(dir.join("query.sql"), "select 1\n").unwrap();
+    let output = commit();
+    assert!(output.status.success());
+
+    // The formatted file is committed, and other files are left alone
+    let show = |name: &str| {
+        let output = Command::new("git")
+            .current_dir(dir)
+            .args(["show", &format!("HEAD:{name}")])
+            .output()
+            .expect("Failed to run git");
+        String::from_utf8(output.stdout).unwrap()
+    };
+    assert_eq!(show("query.sql"), "SELECT\n    1\n");
+    assert_eq!(show("notes.txt"), "select 1\n");
+
+    // A formatted staged file passes the check whatever the working tree holds
+    sleek_command()
+        .current_dir(dir)
+        .args(["install-hook", "--force"])
+        .output()
+        .expect("Failed to execute sleek");
+    fs::write(dir.join("query.sql"), "SELECT\n    2\n").unwrap();
+    git(dir, &["add", "query.sql"]);
+    fs::write(dir.join("query.sql"), "select 3\n").unwrap();
+    assert!(commit().status.success());
+    assert_eq!(show("query.sql"), "SELECT\n    2\n");
+}
@@ -0,0 +1,178 @@
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+use sleek::cache::Cache;
+use sleek::verify::verify;
+use sleek::{Config, Dialect, Language, check_str, format_ranges, format_str};
+
+fn run_sleek_with_stdin(args: &[&str], input: &[u8]) -> String {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_sleek"))
+        .args(args)
+        .arg("--no-config")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .expect("Failed to start sleek");
+
+    child
+        .stdin
+        .as_mut()
+        .unwrap()
+        .write_all(input)
+        .expect("Failed to write to stdin");
+
+    let output = child.wait_with_output().expect("Failed to get output");
+    String::from_utf8(output.stdout).unwrap()
+}
+
+#[test]
+fn test_format_str_with_default_config() {
+    let formatted = format_str("select * from users", &Config::default()).unwrap();
+    assert_eq!(formatted, "SELECT\n    *\nFROM\n    users\n");
+}
+
+#[test]
+fn test_format_str_with_custom_config() {
+    let mut config = Config::default();
+    config.indent_spaces = 2;
+    config.uppercase = false;
+    config.lines_between_queries = 1;
+    config.trailing_newline = false;
+
+    let formatted = format_str("select * from users; select 1;", &config).unwrap();
+    assert_eq!(formatted, "select\n  *\nfrom\n  users;\nselect\n  1;");
+}
+
+#[test]
+fn test_check_str() {
+    let config = Config::default();
+    assert!(check_str("SELECT\n    *\nFROM\n    users\n", &config).unwrap());
+    assert!(!check_str("select * from users", &config).unwrap());
+    assert!(
+        !check_str("SELECT\n    *\nFROM\n    users", &config).unwrap(),
+        "missing trailing newline should fail the check"
+    );
+}
+
+#[test]
+fn test_library_matches_cli() {
+    let inputs = [
+        "select * from users where id = 1",
+        "select a, b from t; insert into t values (1, 2);",
+        "select 1;\n-- sleek: skip\nselect   2;\nselect 3;",
+        "",
+    ];
+
+    for input in inputs {
+        let mut config = Config::default();
+        assert_eq!(
+            format_str(input, &config).unwrap(),
+            run_sleek_with_stdin(&[], input.as_bytes()),
+            "default settings should match for {input:?}"
+        );
+
+        config.indent_spaces = 2;
+        config.uppercase = false;
+        assert_eq!(
+            format_str(input, &config).unwrap(),
+            run_sleek_with_stdin(&["-i", "2", "-U", "false"], input.as_bytes()),
+            "custom settings should match for {input:?}"
+        );
+    }
+}
+
+#[test]
+fn test_verify() {
+    let original = "select a, 'x  y' from t -- note\nwhere b = 1";
+    let formatted = format_str(original, &Config::default()).unwrap();
+    assert_eq!(verify(original, &formatted, Language::Sql, None), Ok(()));
+
+    let mismatch = verify(
+        "select a,\n  'x  y' from t",
+        "SELECT a, 'x y' FROM t",
+        Language::Sql,
+        None,
+    )
+    .unwrap_err();
+    assert_eq!((mismatch.line, mismatch.column), (2, 3));
+    assert_eq!(mismatch.expected, "`'x  y'`");
+    assert_eq!(mismatch.found, "`'x y'`");
+
+    let mismatch = verify("select 1", "SELECT", Language::Sql, None).unwrap_err();
+    assert_eq!(mismatch.found, "end of input");
+
+    // Splitting a variable or a multi-character operator changes the query
+    let mismatch = verify(
+        "select x from t where b=@p",
+        "SELECT x FROM t WHERE b =@ p",
+        Language::Sql,
+        Some(Dialect::Tsql),
+    )
+    .unwrap_err();
+    assert_eq!(
+        (mismatch.expected.as_str(), mismatch.found.as_str()),
+        ("`=`", "`=@`")
+    );
+    let mismatch = verify("select @@version", "SELECT @@ version", Language::Sql, None);
+    assert_eq!(mismatch.unwrap_err().expected, "`@@version`");
+    assert!(verify("select a->>'k'", "SELECT a - >> 'k'", Language::Sql, None).is_err());
+    assert!(verify("select a::int", "SELECT a: :int", Language::Sql, None).is_err());
+    assert_eq!(
+        verify(
+            "select a->>'k', b::int, c=-1, d<=@p",
+            "SELECT a ->> 'k', b::int, c =- 1, d <= @p",
+            Language::Sql,
+            None
+        ),
+        Ok(())
+    );
+
+    // format_str returns output the CLI would refuse, for verify to catch
+    let formatted = format_str("select 0x1F", &Config::default()).unwrap();
+    assert!(verify("select 0x1F", &formatted, Language::Sql, None).is_err());
+}
+
+#[test]
+fn test_cache_round_trip() {
+    let dir = tempfile::TempDir::new().unwrap();
+    let mut config = Config::default();
+    let formatted = Cache::key(b"SELECT\n    1\n", &config);
+    config.uppercase = false;
+    let other = Cache::key(b"SELECT\n    1\n", &config);
+    assert_ne!(formatted, other, "settings should be part of the key");
+
+    let cache = Cache::load(dir.path()).unwrap();
+    assert!(!cache.contains(formatted));
+    cache.insert(formatted);
+    cache.save().unwrap();
+
+    let cache = Cache::load(dir.path()).unwrap();
+    assert!(cache.contains(formatted));
+    assert!(!cache.contains(other));
+
+    // Keys are kept as 128-bit digests in a file versioned by its name
+    let saved = std::fs::read_to_string(dir.path().join("formatted-v2")).unwrap();
+    assert_eq!(saved, format!("{formatted:032x}\n"));
+
+    assert!(Cache::clear(dir.path()).unwrap());
+    assert!(!Cache::clear(dir.path()).unwrap());
+    assert!(!Cache::load(dir.path()).unwrap().contains(formatted));
+}
+
+#[test]
+fn test_format_ranges() {
+    let input = "select 1;\r\nselect  2; select 3;\r\n\r\n  select 4;\r\n";
+    let edits = format_ranges(input, &[13..14, 40..40], &Config::default()).unwrap();
+
+    let ranges: Vec<_> = edits.iter().map(|edit| edit.range.clone()).collect();
+    assert_eq!(ranges, [11..21, 37..46]);
+    // Each statement keeps the line endings and indentation of the input
+    assert_eq!(edits[0].text, "SELECT\r\n    2;");
+    assert_eq!(edits[1].text, "SELECT\r\n      4;");
+
+    assert!(
+        format_ranges(input, &[], &Config::default())
+            .unwrap()
+            .is_empty()
+    );
+}
@@ -0,0 +1,395 @@
+use std::fs;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::path::Path;
+use std::process::{Child, ChildStdout, Command, Stdio};
+
+use serde_json::{Value, json};
+use tempfile::TempDir;
+
+struct LspClient {
+    child: Child,
+    stdout: BufReader<ChildStdout>,
+    next_id: u64,
+}
+
+impl LspClient {
+    fn start(args: &[&str]) -> Self {
+        let mut child = Command::new(env!("CARGO_BIN_EXE_sleek"))
+            .arg("lsp")
+            .args(args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .expect("Failed to start sleek lsp");
+        let stdout = BufReader::new(child.stdout.take().unwrap());
+
+        let mut client = LspClient {
+            child,
+            stdout,
+            next_id: 0,
+        };
+        client.request("initialize", json!({ "capabilities": {} }));
+        client.notify("initialized", json!({}));
+        client
+    }
+
+    fn send(&mut self, message: Value) {
+        let body = message.to_string();
+        let stdin = self.child.stdin.as_mut().unwrap();
+        write!(stdin, "Content-Length: {}\r\n\r\n{body}", body.len()).unwrap();
+        stdin.flush().unwrap();
+    }
+
+    fn receive(&mut self) -> Value {
+        let mut length = 0;
+        loop {
+            let mut header = String::new();
+            self.stdout.read_line(&mut header).unwrap();
+            let header = header.trim();
+            if header.is_empty() {
+                break;
+            }
+            if let Some(value) = header.strip_prefix("Content-Length: ") {
+                length = value.parse().unwrap();
+            }
+        }
+
+        let mut body = vec![0; length];
+        self.stdout.read_exact(&mut body).unwrap();
+        serde_json::from_slice(&body).unwrap()
+    }
+
+    fn request(&mut self, method: &str, params: Value) -> Value {
+        self.next_id += 1;
+        let id = self.next_id;
+        self.send(json!({ "jsonrpc": "2.0", "id": id, "method": method, "params": params }));
+
+        loop {
+            let message = self.receive();
+            if message["id"] == id {
+                return message;
+            }
+        }
+    }
+
+    fn notify(&mut self, method: &str, params: Value) {
+        self.send(json!({ "jsonrpc": "2.0", "method": method, "params": params }));
+    }
+
+    fn open(&mut self, uri: &str, text: &str) -> Value {
+        self.notify(
+            "textDocument/didOpen",
+            json!({
+                "textDocument": { "uri": uri, "languageId": "sql", "version": 1, "text": text }
+            }),
+        );
+        self.receive()
+    }
+
+    fn shutdown(mut self) {
+        self.request("shutdown", Value::Null);
+        self.notify("exit", Value::Null);
+        let status = self.child.wait().unwrap();
+        assert!(status.success(), "server should exit cleanly");
+    }
+}
+
+fn file_uri(path: &Path) -> String {
+    format!("file://{}", path.display())
+}
+
+#[test]
+fn test_lsp_formatting() {
+    let mut client = LspClient::start(&["--no-config"]);
+    client.open("untitled:query.sql", "select * from users");
+
+    let response = client.request(
+        "textDocument/formatting",
+        json!({
+            "textDocument": { "uri": "untitled:query.sql" },
+            "options": { "tabSize": 4, "insertSpaces": true }
+        }),
+    );
+
+    assert_eq!(
+        response["result"],
+        json!([{
+            "range": {
+                "start": { "line": 0, "character": 0 },
+                "end": { "line": 0, "character": 19 }
+            },
+            "newText": "SELECT\n    *\nFROM\n    users\n"
+        }])
+    );
+
+    client.shutdown();
+}
+
+#[test]
+fn test_lsp_formatting_formatted_document_has_no_edits() {
+    let mut client = LspClient::start(&["--no-config"]);
+    client.open("untitled:query.sql", "SELECT\n    *\nFROM\n    users\n");
+
+    let response = client.request(
+        "textDocument/formatting",
+        json!({
+            "textDocument": { "uri": "untitled:query.sql" },
+            "options": { "tabSize": 4, "insertSpaces": true }
+        }),
+    );
+
+    assert_eq!(response["result"], json!([]));
+    client.shutdown();
+}
+
+#[test]
+fn test_lsp_range_formatting_only_touches_selected_statement() {
+    let mut client = LspClient::start(&["--no-config"]);
+    client.open(
+        "untitled:query.sql",
+        "select   1;\nselect * from users;\nselect   2;\n",
+    );
+
+    let response = client.request(
+        "textDocument/rangeFormatting",
+        json!({
+            "textDocument": { "uri": "untitled:query.sql" },
+            "range": {
+                "start": { "line": 1, "character": 2 },
+                "end": { "line": 1, "character": 5 }
+            },
+            "options": { "tabSize": 4, "insertSpaces": true }
+        }),
+    );
+
+    assert_eq!(
+        response["result"],
+        json!([{
+            "range": {
+                "start": { "line": 1, "character": 0 },
+                "end": { "line": 1, "character": 20 }
+            },
+            "newText": "SELECT\n    *\nFROM\n    users;"
+        }])
+    );
+
+    client.shutdown();
+}
+
+#[test]
+fn test_lsp_on_type_formatting() {
+    let mut client = LspClient::start(&["--no-config"]);
+    client.open("untitled:query.sql", "select 1;\nselect * from users;");
+
+    let response = client.request(
+        "textDocument/onTypeFormatting",
+        json!({
+            "textDocument": { "uri": "untitled:query.sql" },
+            "position": { "line": 1, "character": 20 },
+            "ch": ";",
+            "options": { "tabSize": 4, "insertSpaces": true }
+        }),
+    );
+
+    assert_eq!(
+        response["result"],
+        json!([{
+            "range": {
+                "start": { "line": 1, "character": 0 },
+                "end": { "line": 1, "character": 20 }
+            },
+            "newText": "SELECT\n    *\nFROM\n    users;"
+        }])
+    );
+
+    client.shutdown();
+}
+
+#[test]
+fn test_lsp_publishes_diagnostics_for_unformatted_regions() {
+    let mut client = LspClient::start(&["--no-config"]);
+
+    let notification = client.open("untitled:query.sql", "SELECT\n    1;\n\nselect 2;\n");
+    assert_eq!(notification["method"], "textDocument/publishDiagnostics");
+    let diagnostics = notification["params"]["diagnostics"].as_array().unwrap();
+    assert_eq!(diagnostics.len(), 1);
+    assert_eq!(diagnostics[0]["source"], "sleek");
+    assert_eq!(
+        diagnostics[0]["range"],
+        json!({
+            "start": { "line": 3, "character": 0 },
+            "end": { "line": 4, "character": 0 }
+        })
+    );
+
+    client.notify(
+        "textDocument/didChange",
+        json!({
+            "textDocument": { "uri": "untitled:query.sql", "version": 2 },
+            "contentChanges": [{ "text": "SELECT\n    1;\n\nSELECT\n    2;\n" }]
+        }),
+    );
+    let notification = client.receive();
+    assert_eq!(notification["params"]["diagnostics"], json!([]));
+
+    client.shutdown();
+}
+
+#[test]
+fn test_lsp_does_not_offer_formatting_that_changes_tokens() {
+    let mut client = LspClient::start(&["--no-config"]);
+
+    // sqlformat splits the hex literal into `0 x1F`
+    let notification = client.open("untitled:query.sql", "select 0x1F;\n");
+    assert_eq!(notification["params"]["diagnostics"], json!([]));
+
+    let response = client.request(
+        "textDocument/formatting",
+        json!({
+            "textDocument": { "uri": "untitled:query.sql" },
+            "options": { "tabSize": 4, "insertSpaces": true }
+        }),
+    );
+    assert_eq!(response["result"], json!([]));
+
+    let response = client.request(
+        "textDocument/rangeFormatting",
+        json!({
+            "textDocument": { "uri": "untitled:query.sql" },
+            "range": {
+                "start": { "line": 0, "character": 0 },
+                "end": { "line": 0, "character": 12 }
+            },
+            "options": { "tabSize": 4, "insertSpaces": true }
+        }),
+    );
+    assert_eq!(response["result"], json!([]));
+
+    client.shutdown();
+}
+
+#[test]
+fn test_lsp_formats_sql_blocks_of_markdown_documents() {
+    let mut client = LspClient::start(&["--no-config"]);
+
+    let text = "# Queries\n\n```sql\nselect * from users\n```\n";
+    let notification = client.open("untitled:README.md", text);
+    let diagnostics = notification["params"]["diagnostics"].as_array().unwrap();
+    assert_eq!(diagnostics.len(), 1);
+    assert_eq!(diagnostics[0]["range"]["start"]["line"], 3);
+
+    let response = client.request(
+        "textDocument/formatting",
+        json!({
+            "textDocument": { "uri": "untitled:README.md" },
+            "options": { "tabSize": 4, "insertSpaces": true }
+        }),
+    );
+    assert_eq!(
+        response["result"],
+        json!([{
+            "range": {
+                "start": { "line": 3, "character": 0 },
+                "end": { "line": 4, "character": 0 }
+            },
+            "newText": "SELECT\n    *\nFROM\n    users\n"
+        }])
+    );
+
+    let response = client.request(
+        "textDocument/rangeFormatting",
+        json!({
+            "textDocument": { "uri": "untitled:README.md" },
+            "range": {
+                "start": { "line": 3, "character": 0 },
+                "end": { "line": 3, "character": 6 }
+            },
+            "options": { "tabSize": 4, "insertSpaces": true }
+        }),
+    );
+    assert!(
+        response["error"]["message"]
+            .as_str()
+            .unwrap()
+            .contains("only supported for SQL")
+    );
+
+    client.shutdown();
+}
+
+#[test]
+fn test_lsp_uses_config_discovery() {
+    let temp_dir = TempDir::new().unwrap();
+    fs::write(temp_dir.path().join("sleek.toml"), "indent-spaces = 2\n").unwrap();
+    let uri = file_uri(&temp_dir.path().join("query.sql"));
+
+    let mut client = LspClient::start(&[]);
+    client.open(&uri, "select * from users");
+
+    let response = client.request(
+        "textDocument/formatting",
+        json!({
+            "textDocument": { "uri": uri },
+            "options": { "tabSize": 4, "insertSpaces": true }
+        }),
+    );
+
+    assert_eq!(
+        response["result"][0]["newText"],
+        "SELECT\n  *\nFROM\n  users\n"
+    );
+    client.shutdown();
+}
+
+#[test]
+fn test_lsp_invalid_params_do_not_stop_the_server() {
+    let mut client = LspClient::start(&["--no-config"]);
+
+    let response = client.request(
+        "textDocument/formatting",
+        json!({ "options": { "tabSize": 4, "insertSpaces": true } }),
+    );
+    assert_eq!(response["error"]["code"], -32602);
+    assert!(
+        response["error"]["message"]
+            .as_str()
+            .unwrap()
+            .contains("textDocument")
+    );
+
+    // Invalid notifications are ignored
+    client.notify("textDocument/didOpen", json!({ "textDocument": {} }));
+
+    client.open("untitled:query.sql", "select 1");
+    let response = client.request(
+        "textDocument/formatting",
+        json!({
+            "textDocument": { "uri": "untitled:query.sql" },
+            "options": { "tabSize": 4, "insertSpaces": true }
+        }),
+    );
+    assert_eq!(response["result"][0]["newText"], "SELECT\n    1\n");
+
+    client.shutdown();
+}
+
+#[test]
+fn test_lsp_range_formatting_keeps_crlf_line_endings() {
+    let mut client = LspClient::start(&["--no-config"]);
+    client.open("untitled:query.sql", "select 1;\r\nselect   2;\r\n");
+
+    let response = client.request(
+        "textDocument/rangeFormatting",
+        json!({
+            "textDocument": { "uri": "untitled:query.sql" },
+            "range": {
+                "start": { "line": 1, "character": 0 },
+                "end": { "line": 1, "character": 3 }
+            },
+            "options": { "tabSize": 4, "insertSpaces": true }
+        }),
+    );
+
+    assert_eq!(response["result"][0]["newText"], "SELECT\r\n    2;");
+    client.shutdown();
+}